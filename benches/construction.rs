@@ -0,0 +1,26 @@
+//
+// Compares construction time of a 1M-leaf tree hashed with SHA-256 against
+// the same tree hashed with BLAKE3.
+//
+// Run with: cargo bench --features blake3 --bench construction
+//
+
+use merkle_tree_rust_0::MerkleTree;
+use std::time::Instant;
+
+const LEAVES: usize = 1_000_000;
+
+fn main() {
+    let data: Vec<Vec<u8>> = (0..LEAVES).map(|i| format!("leaf {}", i).into_bytes()).collect();
+
+    let start = Instant::now();
+    let sha256 = MerkleTree::new(data.clone());
+    let sha256_time = start.elapsed();
+
+    let start = Instant::now();
+    let blake3 = MerkleTree::new_blake3(data);
+    let blake3_time = start.elapsed();
+
+    println!("SHA-256: {} leaves in {:?}, root {}", LEAVES, sha256_time, sha256.root_hex().unwrap());
+    println!("BLAKE3:  {} leaves in {:?}, root {}", LEAVES, blake3_time, blake3.root_hex().unwrap());
+}
@@ -0,0 +1,51 @@
+//
+// Compares the heap memory held by a 1M-leaf tree before and after freezing
+// it, using a counting allocator.
+//
+// Run with: cargo bench --bench frozen
+//
+
+use merkle_tree_rust_0::MerkleTree;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+const LEAVES: usize = 1_000_000;
+
+/// Tracks the number of bytes currently allocated
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn main() {
+    // The input is freed while the tree is built, so the baseline is taken
+    // before it exists; anything of it that is kept counts as the tree's
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let data: Vec<Vec<u8>> = (0..LEAVES).map(|i| format!("leaf {}", i).into_bytes()).collect();
+    let tree = MerkleTree::new(data);
+    let tree_bytes = ALLOCATED.load(Ordering::Relaxed) - before;
+
+    let start = Instant::now();
+    let frozen = tree.freeze();
+    let freeze_time = start.elapsed();
+    let frozen_bytes = ALLOCATED.load(Ordering::Relaxed) - before;
+
+    println!("MerkleTree:       {} leaves in {} bytes", LEAVES, tree_bytes);
+    println!("FrozenMerkleTree: {} leaves in {} bytes", frozen.leaf_count(), frozen_bytes);
+    println!("Freezing took {:?}", freeze_time);
+}
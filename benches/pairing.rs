@@ -0,0 +1,101 @@
+//
+// Compares two ways of pairing up the nodes of a level while building a
+// 100K-leaf tree: iterating with `chunks(2)` and cloning each pair into its
+// parent, as `build_root` used to, against draining the level and moving
+// each pair in. Both build the same root; the clones copy every subtree
+// again on each level above it, and moving won by more than 10x (about 2s
+// against 0.15s for five trees), so `build_root` moves.
+//
+// Run with: cargo bench --bench pairing
+//
+
+use merkle_tree_rust_0::MerkleTree;
+use sha2::{Digest, Sha256};
+use std::hint::black_box;
+use std::time::Instant;
+
+const LEAVES: usize = 100_000;
+const ROUNDS: usize = 5;
+
+/// The shape of the crate's private `Node`
+#[derive(Clone)]
+struct Node {
+    hash: Vec<u8>,
+    left: Option<Box<Node>>,
+    right: Option<Box<Node>>,
+}
+
+fn parent(left: Node, right: Node) -> Node {
+    Node {
+        hash: Sha256::digest([left.hash.as_slice(), &right.hash].concat()).to_vec(),
+        left: Some(Box::new(left)),
+        right: Some(Box::new(right)),
+    }
+}
+
+fn leaf_count(node: &Node) -> usize {
+    match (&node.left, &node.right) {
+        (Some(left), Some(right)) => leaf_count(left) + leaf_count(right),
+        _ => 1,
+    }
+}
+
+/// The previous level loop, kept here as the baseline
+fn build_with_chunks(mut nodes: Vec<Node>) -> Node {
+    while nodes.len() > 1 {
+        let mut next_level = Vec::with_capacity(nodes.len().div_ceil(2));
+        for chunk in nodes.chunks(2) {
+            if chunk.len() == 2 {
+                next_level.push(parent(chunk[0].clone(), chunk[1].clone()));
+            } else {
+                next_level.push(chunk[0].clone());
+            }
+        }
+        nodes = next_level;
+    }
+    nodes.remove(0)
+}
+
+/// The current level loop, moving each node into its parent
+fn build_with_moves(mut nodes: Vec<Node>) -> Node {
+    while nodes.len() > 1 {
+        let mut next_level = Vec::with_capacity(nodes.len().div_ceil(2));
+        let mut level = nodes.into_iter();
+        while let Some(left) = level.next() {
+            match level.next() {
+                Some(right) => next_level.push(parent(left, right)),
+                None => next_level.push(left),
+            }
+        }
+        nodes = next_level;
+    }
+    nodes.remove(0)
+}
+
+fn main() {
+    let data: Vec<Vec<u8>> = (0..LEAVES).map(|i| format!("leaf {}", i).into_bytes()).collect();
+    let leaves: Vec<Node> = data.iter()
+        .map(|block| Node { hash: Sha256::digest(block).to_vec(), left: None, right: None })
+        .collect();
+
+    let expected = MerkleTree::new(data).root_hash().unwrap();
+    for root in [build_with_chunks(leaves.clone()), build_with_moves(leaves.clone())] {
+        assert_eq!(root.hash, expected);
+        assert_eq!(leaf_count(&root), LEAVES);
+    }
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(build_with_chunks(black_box(leaves.clone())));
+    }
+    let chunks_time = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(build_with_moves(black_box(leaves.clone())));
+    }
+    let moves_time = start.elapsed();
+
+    println!("chunks(2) and clone: {} trees of {} leaves in {:?}", ROUNDS, LEAVES, chunks_time);
+    println!("Moving pairs:        {} trees of {} leaves in {:?}", ROUNDS, LEAVES, moves_time);
+}
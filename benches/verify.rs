@@ -0,0 +1,68 @@
+//
+// Compares verifying a height-32 proof with a fresh SHA-256 hasher and hash
+// buffer per level, as `verify_proof` used to, against the current version
+// that reuses one hasher throughout.
+//
+// Run with: cargo bench --bench verify
+//
+
+use merkle_tree_rust_0::verify_proof;
+use sha2::{Digest, Sha256};
+use std::hint::black_box;
+use std::time::Instant;
+
+const HEIGHT: usize = 32;
+const ROUNDS: usize = 200_000;
+
+/// The previous verification loop, kept here as the baseline
+fn verify_fresh_hasher(root: &[u8], data: &[u8], proof: &[Vec<u8>], proof_path: &[bool]) -> bool {
+    let mut current_hash = Sha256::digest(data).to_vec();
+    for (sibling_hash, &is_right) in proof.iter().zip(proof_path) {
+        let mut hasher = Sha256::new();
+        if is_right {
+            hasher.update(sibling_hash);
+            hasher.update(&current_hash);
+        } else {
+            hasher.update(&current_hash);
+            hasher.update(sibling_hash);
+        }
+        current_hash = hasher.finalize().to_vec();
+    }
+    current_hash == root
+}
+
+fn main() {
+    let data = b"leaf";
+    let proof: Vec<Vec<u8>> = (0..HEIGHT)
+        .map(|level| Sha256::digest(format!("sibling {}", level)).to_vec())
+        .collect();
+    let proof_path: Vec<bool> = (0..HEIGHT).map(|level| level % 3 == 0).collect();
+
+    // Derive the root with the baseline loop's own arithmetic
+    let mut root = Sha256::digest(data).to_vec();
+    for (sibling_hash, &is_right) in proof.iter().zip(&proof_path) {
+        let pair = if is_right {
+            [sibling_hash.as_slice(), &root].concat()
+        } else {
+            [root.as_slice(), sibling_hash].concat()
+        };
+        root = Sha256::digest(pair).to_vec();
+    }
+    assert!(verify_fresh_hasher(&root, data, &proof, &proof_path));
+    assert!(verify_proof(&root, data, &proof, &proof_path));
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(verify_fresh_hasher(black_box(&root), data, &proof, &proof_path));
+    }
+    let fresh_time = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(verify_proof(black_box(&root), data, &proof, &proof_path));
+    }
+    let reused_time = start.elapsed();
+
+    println!("Fresh hasher per level: {} proofs of height {} in {:?}", ROUNDS, HEIGHT, fresh_time);
+    println!("Reused hasher:          {} proofs of height {} in {:?}", ROUNDS, HEIGHT, reused_time);
+}
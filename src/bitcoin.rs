@@ -0,0 +1,128 @@
+//
+// Bitcoin partial merkle trees, as sent in `merkleblock` messages
+//
+// A block's transaction tree differs from `MerkleTree`: nodes are hashed
+// with double SHA-256 and no leaf hashing, and an odd node at the end of a
+// level is paired with itself instead of promoted. A partial tree sends the
+// tree depth-first as one flag bit per visited node, least significant bit
+// first, and a list of hashes. A set flag on an internal node means some
+// transaction below it matched and its children follow; otherwise the
+// node's hash is taken from the list. A set flag on a leaf marks a matched
+// transaction.
+//
+// Hashes are in Bitcoin's internal byte order, the reverse of how txids and
+// block hashes are usually displayed.
+//
+
+use sha2::{Digest, Sha256};
+
+use crate::{Hash, MerkleError, HASH_LEN};
+
+/// A parsed Bitcoin `CPartialMerkleTree`
+///
+/// This is a type of its own rather than a `PartialMerkleTree`: Bitcoin
+/// pairs an odd node with itself where `PartialMerkleTree` promotes it, so
+/// the same subtree hashes lead to different roots, and the flags fix which
+/// hashes are sent instead of letting them arrive in any order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitcoinPartialMerkleTree {
+    root: Hash,
+    matched: Vec<Hash>,
+}
+
+impl BitcoinPartialMerkleTree {
+    /// Parse the packed flag bytes and concatenated 32-byte hashes of a
+    /// partial tree over a block of `tx_count` transactions
+    ///
+    /// Fails if the flags and hashes do not describe exactly one tree of that
+    /// size, including the duplicated-subtree trick of CVE-2012-2459.
+    pub fn from_bitcoin_bytes(
+        flags: &[u8],
+        hashes: &[u8],
+        tx_count: u32,
+    ) -> Result<Self, MerkleError> {
+        if tx_count == 0 {
+            return Err(MerkleError::EmptyTree);
+        }
+        if !hashes.len().is_multiple_of(HASH_LEN) {
+            return Err(MerkleError::BadFormat("partial tree hashes are not 32 bytes each"));
+        }
+        let hash_count = hashes.len() / HASH_LEN;
+        if hash_count > tx_count as usize || hash_count > flags.len() * 8 {
+            return Err(MerkleError::BadFormat("partial tree has too many hashes"));
+        }
+
+        let mut parser = Parser {
+            tx_count: tx_count as usize,
+            flags,
+            bits_used: 0,
+            hashes: hashes.chunks_exact(HASH_LEN),
+            matched: Vec::new(),
+        };
+        let mut height = 0;
+        while parser.width(height) > 1 {
+            height += 1;
+        }
+        let root = parser.node(height, 0)?;
+
+        if parser.hashes.len() != 0 {
+            return Err(MerkleError::BadFormat("partial tree has unused hashes"));
+        }
+        if parser.bits_used.div_ceil(8) != flags.len() {
+            return Err(MerkleError::BadFormat("partial tree has unused flag bytes"));
+        }
+        Ok(BitcoinPartialMerkleTree { root, matched: parser.matched })
+    }
+
+    /// Check the tree against a block's merkle root, returning the matched
+    /// txids in block order if it leads there
+    pub fn verify(&self, root: &[u8]) -> Option<Vec<Hash>> {
+        (self.root == root).then(|| self.matched.clone())
+    }
+}
+
+struct Parser<'a> {
+    tx_count: usize,
+    flags: &'a [u8],
+    bits_used: usize,
+    hashes: std::slice::ChunksExact<'a, u8>,
+    matched: Vec<Hash>,
+}
+
+impl Parser<'_> {
+    /// The number of nodes at `height` above the transactions
+    fn width(&self, height: u32) -> usize {
+        (self.tx_count + (1 << height) - 1) >> height
+    }
+
+    /// Rebuild the hash of the node at `height` and `position`, consuming its
+    /// flag and any hashes below it
+    fn node(&mut self, height: u32, position: usize) -> Result<Hash, MerkleError> {
+        let flag = self.flags.get(self.bits_used / 8)
+            .map(|byte| byte & (1 << (self.bits_used % 8)) != 0)
+            .ok_or(MerkleError::BadFormat("partial tree ran out of flag bits"))?;
+        self.bits_used += 1;
+
+        if height == 0 || !flag {
+            let hash = self.hashes.next()
+                .ok_or(MerkleError::BadFormat("partial tree ran out of hashes"))?
+                .to_vec();
+            if height == 0 && flag {
+                self.matched.push(hash.clone());
+            }
+            return Ok(hash);
+        }
+
+        let left = self.node(height - 1, position * 2)?;
+        let right = if position * 2 + 1 < self.width(height - 1) {
+            let right = self.node(height - 1, position * 2 + 1)?;
+            if right == left {
+                return Err(MerkleError::BadFormat("partial tree repeats a subtree"));
+            }
+            right
+        } else {
+            left.clone()
+        };
+        Ok(Sha256::digest(Sha256::digest([left, right].concat())).to_vec())
+    }
+}
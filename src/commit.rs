@@ -0,0 +1,86 @@
+//
+// Merkle Tree of salted commitments
+//
+// Each leaf is the hash of a value followed by a random salt, so the root and
+// the proofs reveal nothing about the values: without the salts, trees over
+// two different sets of values look alike. A leaf is opened by handing out
+// its value, its salt and its proof, and the verifier recomputes the leaf
+// from the value and salt.
+//
+
+use crate::{verify_proof, MerkleTree, Proof};
+
+/// The length in bytes of the salt mixed into each leaf
+pub const SALT_LEN: usize = 32;
+
+/// The random salt mixed into one leaf of a `CommitMerkleTree`
+pub type Salt = [u8; SALT_LEN];
+
+/// A Merkle Tree over salted commitments to values
+#[derive(Debug)]
+pub struct CommitMerkleTree {
+    values: Vec<Vec<u8>>,
+    salts: Vec<Salt>,
+    tree: MerkleTree,
+}
+
+impl CommitMerkleTree {
+    /// Commit to `values` under fresh random salts, one per value
+    ///
+    /// Returns the tree together with a copy of the salts, to be kept
+    /// separately from the values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the operating system's random number generator fails.
+    pub fn commit(values: Vec<Vec<u8>>) -> (Self, Vec<Salt>) {
+        let salts: Vec<Salt> = values.iter()
+            .map(|_| {
+                let mut salt = [0; SALT_LEN];
+                getrandom::fill(&mut salt).expect("system random number generator failed");
+                salt
+            })
+            .collect();
+        (Self::with_salts(values, salts.clone()), salts)
+    }
+
+    /// Commit to `values` under the given salts, e.g. to rebuild a tree from
+    /// stored salts
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not exactly one salt per value.
+    pub fn with_salts(values: Vec<Vec<u8>>, salts: Vec<Salt>) -> Self {
+        assert_eq!(values.len(), salts.len(), "need exactly one salt per value");
+        let leaves = values.iter()
+            .zip(&salts)
+            .map(|(value, salt)| commitment(value, salt))
+            .collect();
+        CommitMerkleTree { values, salts, tree: MerkleTree::new(leaves) }
+    }
+
+    /// Get the root hash of the tree
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        self.tree.root_hash()
+    }
+
+    /// Get the number of committed values
+    pub fn leaf_count(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Open the commitment at `index`, returning its value, salt and proof
+    pub fn open(&self, index: usize) -> Option<(&[u8], Salt, Proof)> {
+        let proof = self.tree.proof(index)?;
+        Some((&self.values[index], self.salts[index], proof))
+    }
+
+    /// Verify an opened `value` and `salt` against a known root hash
+    pub fn verify_opening(root: &[u8], value: &[u8], salt: &Salt, proof: &Proof) -> bool {
+        verify_proof(root, &commitment(value, salt), &proof.siblings, &proof.path_bits())
+    }
+}
+
+fn commitment(value: &[u8], salt: &Salt) -> Vec<u8> {
+    [value, salt].concat()
+}
@@ -0,0 +1,125 @@
+//
+// Compact byte encoding of SHA-256 inclusion proofs
+//
+// A proof is encoded as one byte holding the number of siblings `n`, then
+// the path packed into `ceil(n / 8)` bytes (bit `i % 8` of byte `i / 8`,
+// set when the running hash is the right child), then the `n` sibling
+// hashes of `HASH_LEN` bytes each, from the leaf upwards. The encoding can
+// be verified as it is read, without building a `Proof` first.
+//
+// `Proof::to_bytes` stores a complete `Proof` as its leaf index, 8 bytes
+// little-endian, followed by the compact encoding. `to_flat_bytes` and
+// `verify_flat` are the same encoding under the name other systems know it
+// by, a flat proof.
+//
+
+use crate::{MerkleError, Proof, Side, TreeHasher, DEFAULT_MAX_PROOF_LEN, HASH_LEN};
+
+impl Proof {
+    /// Encode the proof in the compact byte format read by `verify_compact`
+    ///
+    /// The leaf index is not included. Fails if the proof is not well-formed
+    /// (see `validate_shape`).
+    pub fn to_compact(&self) -> Result<Vec<u8>, MerkleError> {
+        self.validate_shape()?;
+
+        let len = self.siblings.len();
+        let mut bytes = Vec::with_capacity(1 + len.div_ceil(8) + len * HASH_LEN);
+        bytes.push(len as u8);
+        bytes.resize(1 + len.div_ceil(8), 0);
+        for (i, _) in self.path.iter().enumerate().filter(|(_, side)| side.is_right()) {
+            bytes[1 + i / 8] |= 1 << (i % 8);
+        }
+        for sibling in &self.siblings {
+            bytes.extend_from_slice(sibling);
+        }
+        Ok(bytes)
+    }
+
+    /// Encode the proof as one flat byte string, the path embedded before
+    /// the siblings; this is the compact encoding, read by `verify_flat`
+    pub fn to_flat_bytes(&self) -> Result<Vec<u8>, MerkleError> {
+        self.to_compact()
+    }
+
+    /// Load a flat proof written by `to_flat_bytes` or `to_compact`, which
+    /// does not record the leaf index, for the leaf at `leaf_index`
+    pub fn from_flat_bytes(flat: &[u8], leaf_index: usize) -> Result<Self, MerkleError> {
+        let (path, siblings) = split_compact(flat)?;
+        Ok(Proof {
+            siblings: siblings.chunks_exact(HASH_LEN).map(<[u8]>::to_vec).collect(),
+            path: (0..siblings.len() / HASH_LEN)
+                .map(|i| Side::from_is_right(path[i / 8] & (1 << (i % 8)) != 0))
+                .collect(),
+            leaf_index,
+        })
+    }
+
+    /// Serialize the whole proof, leaf index included, independently of the
+    /// tree it came from
+    ///
+    /// Fails if the proof is not well-formed (see `validate_shape`).
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MerkleError> {
+        let mut bytes = (self.leaf_index as u64).to_le_bytes().to_vec();
+        bytes.extend(self.to_compact()?);
+        Ok(bytes)
+    }
+
+    /// Load a proof written by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MerkleError> {
+        let (leaf_index, compact) = bytes.split_first_chunk::<8>()
+            .ok_or(MerkleError::BadFormat("truncated proof"))?;
+        let leaf_index = usize::try_from(u64::from_le_bytes(*leaf_index))
+            .map_err(|_| MerkleError::BadFormat("leaf index does not fit in usize"))?;
+
+        Self::from_flat_bytes(compact, leaf_index)
+    }
+}
+
+/// Verify a compact-encoded proof for a data block against a known SHA-256
+/// root hash, parsing the siblings as they are hashed
+///
+/// Returns `Ok(false)` for a well-formed proof that does not lead to `root`,
+/// and an error if the bytes are not a valid encoding.
+pub fn verify_compact(root: &[u8], data: &[u8], compact: &[u8]) -> Result<bool, MerkleError> {
+    let (path, siblings) = split_compact(compact)?;
+
+    let hasher = TreeHasher::default();
+    let mut current_hash = hasher.hash_leaf(data);
+    for (i, sibling) in siblings.chunks_exact(HASH_LEN).enumerate() {
+        let is_right = path[i / 8] & (1 << (i % 8)) != 0;
+        current_hash = if is_right {
+            hasher.hash_children(sibling, &current_hash)
+        } else {
+            hasher.hash_children(&current_hash, sibling)
+        };
+    }
+
+    Ok(current_hash == root)
+}
+
+/// Verify a flat proof from `Proof::to_flat_bytes` for a data block against
+/// a known SHA-256 root hash
+///
+/// Unlike `verify_compact`, malformed bytes are simply a failed verification.
+pub fn verify_flat(root: &[u8], data: &[u8], flat: &[u8]) -> bool {
+    verify_compact(root, data, flat).unwrap_or(false)
+}
+
+/// Split a compact encoding into its packed path and sibling bytes, checking
+/// that their lengths agree with the sibling count
+fn split_compact(compact: &[u8]) -> Result<(&[u8], &[u8]), MerkleError> {
+    let (&len, rest) = compact.split_first()
+        .ok_or(MerkleError::BadFormat("empty compact proof"))?;
+    let len = len as usize;
+    if len > DEFAULT_MAX_PROOF_LEN {
+        return Err(MerkleError::ProofTooLong { len, max: DEFAULT_MAX_PROOF_LEN });
+    }
+
+    let (path, siblings) = rest.split_at_checked(len.div_ceil(8))
+        .ok_or(MerkleError::BadFormat("truncated compact proof"))?;
+    if siblings.len() != len * HASH_LEN {
+        return Err(MerkleError::BadFormat("compact proof has the wrong number of sibling bytes"));
+    }
+    Ok((path, siblings))
+}
@@ -0,0 +1,186 @@
+//
+// Consistency proofs between successive roots of an append-only log
+//
+// The canonical shape splits a node over `n` leaves after the largest power
+// of two below `n`, the same as Certificate Transparency (RFC 6962), so its
+// consistency proofs carry over: the hashes needed to rebuild both the old
+// root over the first `old_size` leaves and the new root over all of them.
+// Verification follows RFC 9162, section 2.1.4.2, with this crate's SHA-256
+// node hashing instead of the RFC's prefixed one.
+//
+// A log that keeps its root after every append can hand out one proof per
+// step, and a verifier holding the first root checks each later root in turn.
+//
+// The tree over the first `version` leaves is also still inside the current
+// one: every node of it is either a node of the current tree or splits into
+// them, so its root and inclusion proofs can be recomputed after later
+// appends.
+//
+
+use crate::{checked_split, Hash, MerkleTree, Node, Proof, Side, TreeHasher};
+
+/// A proof that a tree of `new_size` leaves starts with the first
+/// `old_size` leaves of an earlier tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsistencyProof {
+    pub old_size: usize,
+    pub new_size: usize,
+    pub hashes: Vec<Hash>,
+}
+
+impl<M> MerkleTree<M> {
+    /// Prove that this tree extends the tree over its first `old_size` leaves
+    ///
+    /// Returns `None` if `old_size` is 0 or more than the number of leaves, or
+    /// if a node the proof needs has been pruned.
+    pub fn consistency_proof(&self, old_size: usize) -> Option<ConsistencyProof> {
+        let root = self.root.as_ref()?;
+        if old_size == 0 || old_size > self.leaf_count {
+            return None;
+        }
+
+        let mut hashes = Vec::new();
+        subproof(old_size, root, self.leaf_count, true, &mut hashes)?;
+        Some(ConsistencyProof { old_size, new_size: self.leaf_count, hashes })
+    }
+
+    /// Get the root the tree had when it held only its first `version`
+    /// leaves
+    ///
+    /// Returns `None` if `version` is 0 or more than the number of leaves,
+    /// or if a node it needs has been pruned.
+    pub fn root_at_version(&self, version: usize) -> Option<Hash> {
+        if version == 0 || version > self.leaf_count {
+            return None;
+        }
+        self.range_hash(0, version)
+    }
+
+    /// Prove the leaf at `index` against `root_at_version(version)`, the root
+    /// from when the tree held only its first `version` leaves
+    ///
+    /// Returns `None` unless `index < version <= leaf_count`, or if a node
+    /// the proof needs has been pruned.
+    pub fn prove_at_version(&self, index: usize, version: usize) -> Option<Proof> {
+        if index >= version || version > self.leaf_count {
+            return None;
+        }
+
+        let mut siblings = Vec::new();
+        let mut path = Vec::new();
+        let (mut start, mut end) = (0, version);
+        while end - start > 1 {
+            let mid = start.checked_add(checked_split(end - start)?)?;
+            if index < mid {
+                siblings.push(self.range_hash(mid, end)?);
+                path.push(Side::Left);
+                end = mid;
+            } else {
+                siblings.push(self.range_hash(start, mid)?);
+                path.push(Side::Right);
+                start = mid;
+            }
+        }
+        siblings.reverse();
+        path.reverse();
+        Some(Proof { siblings, path, leaf_index: index })
+    }
+
+    /// Hash the leaves `start..end` as the canonical shape would over just
+    /// those leaves, reusing the nodes of this tree where they match
+    fn range_hash(&self, start: usize, end: usize) -> Option<Hash> {
+        if let Some(hash) = self.subtree_root(start, end) {
+            return Some(hash);
+        }
+        if end - start < 2 {
+            return None;
+        }
+        let mid = start.checked_add(checked_split(end - start)?)?;
+        let left = self.range_hash(start, mid)?;
+        let right = self.range_hash(mid, end)?;
+        Some(self.hasher.hash_children(&left, &right))
+    }
+}
+
+/// Collect the hashes proving that the first `old_size` leaves under `node`,
+/// which covers `width` leaves, form a prefix of it, as RFC 6962's SUBPROOF;
+/// `whole` is set while the old tree's root is one the verifier already has
+fn subproof(
+    old_size: usize,
+    node: &Node,
+    width: usize,
+    whole: bool,
+    hashes: &mut Vec<Hash>,
+) -> Option<()> {
+    if old_size == width {
+        if !whole {
+            hashes.push(node.hash.clone());
+        }
+        return Some(());
+    }
+
+    let (left, right) = node.children()?;
+    let split = checked_split(width)?;
+    if old_size <= split {
+        subproof(old_size, left, split, whole, hashes)?;
+        hashes.push(right.hash.clone());
+    } else {
+        subproof(old_size - split, right, width - split, false, hashes)?;
+        hashes.push(left.hash.clone());
+    }
+    Some(())
+}
+
+/// Check that `new_root` is the root after appending leaves to the tree with
+/// root `prev_root`, returning the number of leaves appended
+///
+/// Hashing is SHA-256 as in `verify_proof`. To check a history of roots,
+/// feed each step's proof in turn, making sure each `old_size` is the
+/// previous step's `new_size`.
+pub fn root_history_delta(
+    prev_root: &[u8],
+    new_root: &[u8],
+    consistency_proof: &ConsistencyProof,
+) -> Option<usize> {
+    let ConsistencyProof { old_size, new_size, hashes } = consistency_proof;
+    let (old_size, new_size) = (*old_size, *new_size);
+    if old_size == 0 || old_size > new_size {
+        return None;
+    }
+    if old_size == new_size {
+        return (hashes.is_empty() && prev_root == new_root).then_some(0);
+    }
+
+    // The proof leaves out the old root when it is a node of the new tree
+    let mut hashes = hashes.iter().map(Vec::as_slice);
+    let first = if old_size.is_power_of_two() { Some(prev_root) } else { hashes.next() }?;
+
+    let hasher = TreeHasher::default();
+    let (mut old_index, mut new_index) = (old_size - 1, new_size - 1);
+    while old_index & 1 == 1 {
+        old_index >>= 1;
+        new_index >>= 1;
+    }
+
+    let (mut old_hash, mut new_hash) = (first.to_vec(), first.to_vec());
+    for hash in hashes {
+        if new_index == 0 {
+            return None;
+        }
+        if old_index & 1 == 1 || old_index == new_index {
+            old_hash = hasher.hash_children(hash, &old_hash);
+            new_hash = hasher.hash_children(hash, &new_hash);
+            while old_index & 1 == 0 && old_index != 0 {
+                old_index >>= 1;
+                new_index >>= 1;
+            }
+        } else {
+            new_hash = hasher.hash_children(&new_hash, hash);
+        }
+        old_index >>= 1;
+        new_index >>= 1;
+    }
+
+    (new_index == 0 && old_hash == prev_root && new_hash == new_root)
+        .then_some(new_size - old_size)
+}
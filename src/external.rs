@@ -0,0 +1,154 @@
+//
+// Building trees too large to hold in memory
+//
+// Leaf hashes are streamed to a temporary file as they arrive. Each level is
+// then read back two hashes at a time and its parents written to a new file,
+// so only a couple of hashes are held in memory at once no matter how many
+// leaves there are. The root is identical to the in-memory construction.
+//
+// The level files live in a directory of the builder's own, created under a
+// random name and, on Unix, readable only by its owner. Files are only ever
+// created new, never opened if they already exist, so nothing placed in the
+// shared temp directory beforehand can be overwritten or have hashes read
+// from it.
+//
+
+use crate::{MerkleError, TreeHasher, MAX_LEAVES};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Computes the root of a tree over more leaves than fit in memory by
+/// spilling every level to a temporary file
+#[derive(Debug)]
+pub struct ExternalMerkleBuilder {
+    /// The builder's private directory, removed with everything in it on drop
+    dir: PathBuf,
+    hasher: TreeHasher,
+    leaves: BufWriter<File>,
+    leaf_count: usize,
+    hash_len: usize,
+}
+
+impl ExternalMerkleBuilder {
+    /// Create a builder that keeps its level files in the system temp directory
+    pub fn new() -> io::Result<Self> {
+        Self::in_dir(std::env::temp_dir())
+    }
+
+    /// Create a builder that keeps its level files in a new private
+    /// directory under `dir`
+    pub fn in_dir(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = create_private_dir(dir.as_ref())?;
+        let leaves = match create_new(&level_path(&dir, 0)) {
+            Ok(file) => BufWriter::new(file),
+            Err(error) => {
+                let _ = fs::remove_dir_all(&dir);
+                return Err(error);
+            }
+        };
+        let hasher = TreeHasher::default();
+        let hash_len = hasher.hash_leaf(&[]).len();
+
+        Ok(ExternalMerkleBuilder { dir, hasher, leaves, leaf_count: 0, hash_len })
+    }
+
+    /// Hash a data block and append it as the next leaf
+    ///
+    /// Fails with `MerkleError::TooManyLeaves` (wrapped in an `io::Error`)
+    /// once the tree already holds `MAX_LEAVES` leaves.
+    pub fn push(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.leaf_count >= MAX_LEAVES {
+            return Err(io::Error::other(MerkleError::TooManyLeaves { max: MAX_LEAVES }));
+        }
+
+        self.leaves.write_all(&self.hasher.hash_leaf(data))?;
+        self.leaf_count += 1;
+        Ok(())
+    }
+
+    /// Get the number of leaves pushed so far
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Build the remaining levels and return the root hash, or `None` if no
+    /// leaves were pushed
+    ///
+    /// The level files are removed afterwards, whether or not this succeeds.
+    pub fn finish(mut self) -> io::Result<Option<Vec<u8>>> {
+        self.leaves.flush()?;
+        if self.leaf_count == 0 {
+            return Ok(None);
+        }
+
+        let mut level = 0;
+        let mut width = self.leaf_count;
+        while width > 1 {
+            self.build_level(level, width)?;
+            fs::remove_file(level_path(&self.dir, level))?;
+            level += 1;
+            width = width.div_ceil(2);
+        }
+
+        let mut root = vec![0; self.hash_len];
+        File::open(level_path(&self.dir, level))?.read_exact(&mut root)?;
+        Ok(Some(root))
+    }
+
+    /// Read the level `width` nodes wide and write its parents to the next one
+    fn build_level(&self, level: usize, width: usize) -> io::Result<()> {
+        let mut input = BufReader::new(File::open(level_path(&self.dir, level))?);
+        let mut output = BufWriter::new(create_new(&level_path(&self.dir, level + 1))?);
+
+        let mut left = vec![0; self.hash_len];
+        let mut right = vec![0; self.hash_len];
+        for _ in 0..width / 2 {
+            input.read_exact(&mut left)?;
+            input.read_exact(&mut right)?;
+            output.write_all(&self.hasher.hash_children(&left, &right))?;
+        }
+        if width % 2 == 1 {
+            // If there's an odd node left, promote it to the next level
+            input.read_exact(&mut left)?;
+            output.write_all(&left)?;
+        }
+
+        output.flush()
+    }
+}
+
+impl Drop for ExternalMerkleBuilder {
+    fn drop(&mut self) {
+        // Clean up whichever level files are left along with the directory
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Create a directory under `parent` that no other builder or user shares,
+/// with a random name so it cannot be created ahead of time
+fn create_private_dir(parent: &Path) -> io::Result<PathBuf> {
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+    loop {
+        let mut suffix = [0; 16];
+        getrandom::fill(&mut suffix).map_err(io::Error::from)?;
+        let dir = parent.join(format!("merkle-{}", hex::encode(suffix)));
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Create a file for writing, failing if anything is already at `path`
+fn create_new(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+fn level_path(dir: &Path, level: usize) -> PathBuf {
+    dir.join(format!("level-{}.bin", level))
+}
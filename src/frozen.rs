@@ -0,0 +1,95 @@
+//
+// Compact, read-only Merkle Trees
+//
+// A `MerkleTree` stores boxed nodes linked by pointers, which is convenient
+// for rebuilding but costs an allocation and two pointers per node. Once a
+// tree is only queried, every level's hashes can live in one boxed slice,
+// level after level, and proofs are read off by index arithmetic.
+//
+
+use crate::{check_proof_shape, Hash, TreeHasher};
+
+/// An immutable Merkle Tree stored as a flat array of hashes
+#[derive(Debug, Clone)]
+pub struct FrozenMerkleTree {
+    /// Hashes of every level, leaves first and the root last
+    pub(crate) hashes: Box<[Hash]>,
+    /// Start of each level within `hashes`
+    offsets: Box<[usize]>,
+    /// Number of nodes on each level
+    widths: Box<[usize]>,
+    hasher: TreeHasher,
+    max_proof_len: usize,
+}
+
+impl FrozenMerkleTree {
+    pub(crate) fn new(
+        hashes: Vec<Hash>,
+        widths: Vec<usize>,
+        hasher: TreeHasher,
+        max_proof_len: usize,
+    ) -> Self {
+        let offsets = widths.iter()
+            .scan(0, |start, &width| {
+                let offset = *start;
+                *start += width;
+                Some(offset)
+            })
+            .collect();
+
+        FrozenMerkleTree {
+            hashes: hashes.into_boxed_slice(),
+            offsets,
+            widths: widths.into_boxed_slice(),
+            hasher,
+            max_proof_len,
+        }
+    }
+
+    /// Get the root hash of the tree
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        self.hashes.last().cloned()
+    }
+
+    /// Get the number of leaves in the tree
+    pub fn leaf_count(&self) -> usize {
+        self.widths.first().copied().unwrap_or(0)
+    }
+
+    /// Generate an inclusion proof for the leaf at `index`, identical to the
+    /// one the tree would have produced before it was frozen
+    pub fn generate_proof(&self, index: usize) -> Option<(Vec<Vec<u8>>, Vec<bool>)> {
+        if index >= self.leaf_count() {
+            return None;
+        }
+
+        let mut proof = Vec::new();
+        let mut proof_path = Vec::new();
+        let mut position = index;
+        for (&offset, &width) in self.offsets.iter().zip(self.widths.iter()) {
+            if position % 2 == 1 {
+                proof.push(self.hashes[offset + position - 1].clone());
+                proof_path.push(true);
+            } else if position + 1 < width {
+                proof.push(self.hashes[offset + position + 1].clone());
+                proof_path.push(false);
+            }
+            position /= 2;
+        }
+
+        Some((proof, proof_path))
+    }
+
+    /// Verify that a data block is part of the tree by providing a proof
+    ///
+    /// Malformed proofs are rejected exactly as by `MerkleTree::verify`.
+    pub fn verify(&self, data: &[u8], proof: &[Vec<u8>], proof_path: &[bool]) -> bool {
+        let Some(root) = self.hashes.last() else {
+            return false;
+        };
+        let hash_lens = (self.hashes[0].len(), self.hasher.node.output_len());
+        let leaf_count = self.leaf_count();
+        check_proof_shape(proof, proof_path, leaf_count, self.max_proof_len, hash_lens).is_ok()
+            && self.hasher.root_from_proof(self.hasher.hash_leaf(data), proof, proof_path) == *root
+    }
+}
@@ -0,0 +1,2426 @@
+//
+// Initial implementation of a Merkle Tree in Rust
+// This code provides a basic structure for creating a Merkle Tree
+//
+// A Merkle Tree is a data structure used for storing and verifying data integrity
+// using the tree's root hash.
+// It is commonly used in blockchain technology and distributed systems.
+//
+// This implementation uses SHA-256 for hashing and provides methods to create
+// a Merkle Tree from a list of data blocks, compute the root hash, and verify
+// the inclusion of a data block in the tree using a proof.
+// It includes a simple example of how to use the Merkle Tree
+// and demonstrates the hashing process using SHA-256.
+//
+// This code is for educational purposes and may not be suitable for production use.
+// It is recommended to use established libraries for cryptographic operations
+// and data structures in real applications.
+//
+
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::digest::{self, FixedOutputReset, Output};
+use sha2::{Sha256, Sha512, Digest};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, Read};
+use std::mem;
+use std::path::Path;
+use std::sync::OnceLock;
+use subtle::ConstantTimeEq;
+
+mod bitcoin;
+mod commit;
+mod compact;
+mod consistency;
+mod external;
+mod frozen;
+mod mmr;
+mod multiproof;
+mod partial;
+mod progression;
+mod proof;
+mod root;
+mod serialization;
+mod snapshot;
+mod sorted;
+mod structure;
+mod writer;
+
+pub use bitcoin::BitcoinPartialMerkleTree;
+pub use commit::{CommitMerkleTree, Salt, SALT_LEN};
+pub use compact::{verify_compact, verify_flat};
+pub use consistency::{root_history_delta, ConsistencyProof};
+pub use external::ExternalMerkleBuilder;
+pub use frozen::FrozenMerkleTree;
+pub use mmr::MerkleMountainRange;
+pub use multiproof::MultiProof;
+pub use partial::PartialMerkleTree;
+pub use progression::RootProgression;
+pub use proof::{PathConvention, Proof, Side};
+pub use root::RootHash;
+pub use snapshot::Snapshot;
+pub use sorted::{decode_entry, encode_entry, AbsenceProof, NeighborProof, SortedMerkleTree};
+pub use structure::MerkleStructure;
+pub use writer::MerkleWriter;
+
+/// Hash functions available for hashing leaves and internal nodes
+#[derive(Clone, PartialEq, Eq, Default)]
+pub enum HashAlgorithm {
+    /// SHA-256, the default for both leaves and internal nodes
+    #[default]
+    Sha256,
+    /// SHA-512, producing 64-byte hashes
+    Sha512,
+    /// SHA-256 applied twice, as Bitcoin hashes its transaction trees
+    ///
+    /// Bitcoin pairs an odd node with itself where this crate promotes it,
+    /// so a tree over a block's txids only has the block's Merkle root when
+    /// every level pairs up, i.e. for a power-of-two transaction count.
+    DoubleSha256,
+    /// BLAKE3, producing 32-byte hashes
+    ///
+    /// BLAKE3 has a tree mode of its own, but here it is only used as a flat
+    /// hash function over each leaf and each pair of child hashes.
+    #[cfg(feature = "blake3")]
+    Blake3,
+    /// HMAC-SHA256 under the given key, producing 32-byte MACs
+    ///
+    /// Only holders of the key can compute or check the tree's hashes. The
+    /// key is never serialized, and is hidden from `Debug` output.
+    HmacSha256(Vec<u8>),
+}
+
+impl fmt::Debug for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HashAlgorithm::Sha256 => write!(f, "Sha256"),
+            HashAlgorithm::Sha512 => write!(f, "Sha512"),
+            HashAlgorithm::DoubleSha256 => write!(f, "DoubleSha256"),
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => write!(f, "Blake3"),
+            HashAlgorithm::HmacSha256(_) => write!(f, "HmacSha256(..)"),
+        }
+    }
+}
+
+impl HashAlgorithm {
+    /// The identifier recorded for this algorithm in serialized trees
+    fn id(&self) -> u8 {
+        match self {
+            HashAlgorithm::Sha256 => 1,
+            HashAlgorithm::Sha512 => 2,
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => 3,
+            HashAlgorithm::HmacSha256(_) => 4,
+            HashAlgorithm::DoubleSha256 => 5,
+        }
+    }
+
+    /// Look up an algorithm by its serialized identifier
+    ///
+    /// Keyed algorithms are not found, since their key is not serialized.
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(HashAlgorithm::Sha256),
+            2 => Some(HashAlgorithm::Sha512),
+            #[cfg(feature = "blake3")]
+            3 => Some(HashAlgorithm::Blake3),
+            5 => Some(HashAlgorithm::DoubleSha256),
+            _ => None,
+        }
+    }
+
+    /// The length in bytes of the hashes this algorithm produces
+    fn output_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 => 32,
+            HashAlgorithm::Sha512 => 64,
+            HashAlgorithm::DoubleSha256 => 32,
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => 32,
+            HashAlgorithm::HmacSha256(_) => 32,
+        }
+    }
+
+    /// Hash the concatenation of the given byte strings
+    fn hash(&self, parts: &[&[u8]]) -> Vec<u8> {
+        self.hash_stream(|update| {
+            for part in parts {
+                update(part);
+            }
+            Ok(())
+        })
+        .expect("hashing in-memory data cannot fail")
+    }
+
+    /// Hash everything `feed` passes to the update function it is given,
+    /// so input can be hashed piece by piece as it is read
+    fn hash_stream(
+        &self,
+        feed: impl FnOnce(&mut dyn FnMut(&[u8])) -> io::Result<()>,
+    ) -> io::Result<Vec<u8>> {
+        match self {
+            HashAlgorithm::Sha256 => digest_stream::<Sha256>(feed),
+            HashAlgorithm::Sha512 => digest_stream::<Sha512>(feed),
+            HashAlgorithm::DoubleSha256 => {
+                digest_stream::<Sha256>(feed).map(|once| Sha256::digest(once).to_vec())
+            }
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                feed(&mut |data| {
+                    hasher.update(data);
+                })?;
+                Ok(hasher.finalize().as_bytes().to_vec())
+            }
+            HashAlgorithm::HmacSha256(key) => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key)
+                    .expect("HMAC accepts keys of any length");
+                feed(&mut |data| mac.update(data))?;
+                Ok(mac.finalize().into_bytes().to_vec())
+            }
+        }
+    }
+}
+
+/// Hash up from a leaf along a proof with the digest `D`, returning the
+/// implied root hash
+///
+/// A single hasher is reset between levels and the running hash lives in a
+/// fixed-size buffer, so nothing is allocated until the root is returned.
+fn fold_proof<D: Digest + FixedOutputReset>(
+    leaf_hash: Vec<u8>,
+    proof: &[Vec<u8>],
+    proof_path: &[bool],
+) -> Vec<u8> {
+    if proof.is_empty() || proof_path.is_empty() {
+        return leaf_hash;
+    }
+
+    let mut hasher = D::new();
+    let mut current_hash = Output::<D>::default();
+    for (level, (sibling_hash, &is_right)) in proof.iter().zip(proof_path).enumerate() {
+        let running_hash: &[u8] = if level == 0 { &leaf_hash } else { &current_hash };
+        if is_right {
+            Digest::update(&mut hasher, sibling_hash);
+            Digest::update(&mut hasher, running_hash);
+        } else {
+            Digest::update(&mut hasher, running_hash);
+            Digest::update(&mut hasher, sibling_hash);
+        }
+        Digest::finalize_into_reset(&mut hasher, &mut current_hash);
+    }
+
+    current_hash.to_vec()
+}
+
+fn digest_stream<D: Digest>(
+    feed: impl FnOnce(&mut dyn FnMut(&[u8])) -> io::Result<()>,
+) -> io::Result<Vec<u8>> {
+    let mut hasher = D::new();
+    feed(&mut |data| hasher.update(data))?;
+    Ok(hasher.finalize().to_vec())
+}
+
+fn digest_parts<D: Digest>(parts: &[&[u8]]) -> Vec<u8> {
+    let mut hasher = D::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().to_vec()
+}
+
+/// The hash functions a tree applies to its leaves and internal nodes
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct TreeHasher {
+    leaf: HashAlgorithm,
+    node: HashAlgorithm,
+    node_separator: Option<Vec<u8>>,
+    /// Hash each leaf as `len || data`, with the length as 8 bytes
+    /// little-endian, so no two lists of blocks share a leaf encoding
+    length_prefixed_leaves: bool,
+}
+
+impl TreeHasher {
+    /// Hash a data block into a leaf hash
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        if self.length_prefixed_leaves {
+            self.leaf.hash(&[&(data.len() as u64).to_le_bytes(), data])
+        } else {
+            self.leaf.hash(&[data])
+        }
+    }
+
+    /// Hash a data block read from `reader` into a leaf hash, without
+    /// holding the whole block in memory
+    ///
+    /// Length-prefixed leaves need the length first, so their blocks are read
+    /// in full before hashing.
+    fn hash_leaf_reader(&self, mut reader: impl Read) -> io::Result<Vec<u8>> {
+        if self.length_prefixed_leaves {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            return Ok(self.hash_leaf(&data));
+        }
+        self.leaf.hash_stream(|update| {
+            let mut buffer = [0u8; 64 * 1024];
+            loop {
+                match reader.read(&mut buffer) {
+                    Ok(0) => return Ok(()),
+                    Ok(read) => update(&buffer[..read]),
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                    Err(error) => return Err(error),
+                }
+            }
+        })
+    }
+
+    /// Hash a pair of child hashes into their parent's hash
+    fn hash_children(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        match &self.node_separator {
+            Some(separator) => self.node.hash(&[left, separator, right]),
+            None => self.node.hash(&[left, right]),
+        }
+    }
+
+    /// Hash up from a leaf along a proof, returning the implied root hash
+    fn root_from_proof(
+        &self,
+        leaf_hash: Vec<u8>,
+        proof: &[Vec<u8>],
+        proof_path: &[bool],
+    ) -> Vec<u8> {
+        // Plain SHA-2 node hashing can reuse one hasher for every level
+        if self.node_separator.is_none() {
+            match self.node {
+                HashAlgorithm::Sha256 => return fold_proof::<Sha256>(leaf_hash, proof, proof_path),
+                HashAlgorithm::Sha512 => return fold_proof::<Sha512>(leaf_hash, proof, proof_path),
+                _ => {}
+            }
+        }
+
+        let mut current_hash = leaf_hash;
+
+        // Traverse the proof path and compute the root hash
+        for (sibling_hash, &is_right) in proof.iter().zip(proof_path) {
+            // If is_right is true, then current_hash is the right child
+            // If is_right is false, then current_hash is the left child
+            current_hash = if is_right {
+                self.hash_children(sibling_hash, &current_hash)
+            } else {
+                self.hash_children(&current_hash, sibling_hash)
+            };
+        }
+
+        current_hash
+    }
+}
+
+/// A hash value, as produced for leaves and internal nodes
+pub type Hash = Vec<u8>;
+
+/// The sibling `generate_padded_proof` fills fixed-depth proofs with
+pub const PADDING_SIBLING: [u8; HASH_LEN] = [0; HASH_LEN];
+
+/// The longest proof `verify` accepts unless configured otherwise, enough
+/// for a tree of 2^64 leaves
+pub const DEFAULT_MAX_PROOF_LEN: usize = 64;
+
+/// The most leaves a tree may have
+///
+/// Splitting a node over `n` leaves takes the smallest power of two not
+/// below `n`, which beyond this no longer fits in a `usize`, and neither does
+/// the tree's `2n - 1` node count. On 64-bit targets memory runs out long
+/// before this; the limit matters mainly for 32-bit targets, for trees built
+/// on disk with `ExternalMerkleBuilder`, and for leaf counts that come from
+/// untrusted input.
+pub const MAX_LEAVES: usize = usize::MAX / 2 + 1;
+
+/// The length in bytes of a SHA-256 hash
+pub const HASH_LEN: usize = 32;
+
+/// Errors reported when a tree or proof cannot be used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MerkleError {
+    /// The tree has no leaves, so there is no root to verify against
+    EmptyTree,
+    /// The proof and its path have different lengths
+    PathLengthMismatch { proof_len: usize, path_len: usize },
+    /// The proof is longer than the configured maximum depth
+    ProofTooLong { len: usize, max: usize },
+    /// A sibling hash in the proof has the wrong number of bytes
+    InvalidSiblingLength { index: usize, len: usize, expected: usize },
+    /// A pre-computed leaf hash has the wrong number of bytes
+    InvalidLeafHashLength { index: usize, len: usize, expected: usize },
+    /// Leaves that must be strictly increasing are not, starting at `index`
+    UnsortedLeaves { index: usize },
+    /// The leaf index is not within the tree
+    IndexOutOfRange { index: usize, leaf_count: usize },
+    /// The tree would have more than `MAX_LEAVES` leaves
+    TooManyLeaves { max: usize },
+    /// Serialized data is not a tree this version can load, or a tree
+    /// cannot be written in the format
+    BadFormat(&'static str),
+    /// The leaf range `start..end` is not covered by a single node
+    NotASubtree { start: usize, end: usize },
+    /// The data block at `index` is `size` bytes, over the configured limit
+    LeafTooLarge { index: usize, size: usize },
+    /// The proof path does not lead to any leaf of a tree of `leaf_count`
+    /// leaves, e.g. it turns right where the leaf must be a left child
+    ImpossiblePath { leaf_count: usize },
+    /// A root hash has the wrong number of bytes
+    InvalidRootLength { len: usize, expected: usize },
+}
+
+impl fmt::Display for MerkleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MerkleError::EmptyTree => write!(f, "the tree is empty"),
+            MerkleError::PathLengthMismatch { proof_len, path_len } => write!(
+                f,
+                "proof has {} siblings but its path has {} entries",
+                proof_len, path_len
+            ),
+            MerkleError::ProofTooLong { len, max } => {
+                write!(f, "proof has {} siblings, more than the maximum of {}", len, max)
+            }
+            MerkleError::InvalidSiblingLength { index, len, expected } => write!(
+                f,
+                "proof sibling {} is {} bytes long, expected {}",
+                index, len, expected
+            ),
+            MerkleError::InvalidLeafHashLength { index, len, expected } => write!(
+                f,
+                "leaf hash {} is {} bytes long, expected {}",
+                index, len, expected
+            ),
+            MerkleError::UnsortedLeaves { index } => {
+                write!(f, "leaf {} is not greater than the leaf before it", index)
+            }
+            MerkleError::IndexOutOfRange { index, leaf_count } => {
+                write!(f, "leaf index {} is out of range for {} leaves", index, leaf_count)
+            }
+            MerkleError::TooManyLeaves { max } => {
+                write!(f, "a tree cannot have more than {} leaves", max)
+            }
+            MerkleError::BadFormat(reason) => write!(f, "bad serialized tree: {}", reason),
+            MerkleError::NotASubtree { start, end } => {
+                write!(f, "leaves {}..{} are not the leaves of one subtree", start, end)
+            }
+            MerkleError::LeafTooLarge { index, size } => {
+                write!(f, "leaf {} is {} bytes, over the maximum leaf size", index, size)
+            }
+            MerkleError::ImpossiblePath { leaf_count } => {
+                write!(f, "proof path does not lead to a leaf of a {}-leaf tree", leaf_count)
+            }
+            MerkleError::InvalidRootLength { len, expected } => {
+                write!(f, "root hash is {} bytes long, expected {}", len, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MerkleError {}
+
+/// A node in the Merkle Tree
+#[derive(Debug)]
+pub struct Node {
+    hash: Vec<u8>,
+    left: Option<Box<Node>>,
+    right: Option<Box<Node>>,
+}
+
+impl Node {
+    /// Create a new leaf node with the given data
+    fn new_leaf(data: &[u8], hasher: &TreeHasher) -> Self {
+        Node::from_hash(hasher.hash_leaf(data))
+    }
+
+    /// Start hashing a SHA-256 leaf whose data arrives in pieces
+    ///
+    /// The finished leaf has the same hash as if the pieces had been
+    /// concatenated and hashed at once.
+    pub fn new_leaf_builder() -> LeafHasher {
+        LeafHasher { state: Sha256::new() }
+    }
+
+    /// Create a SHA-256 leaf node over the whole contents of the file at
+    /// `path`, memory-mapping it so files larger than memory can be hashed
+    ///
+    /// The hash is the same as for a leaf over the file's bytes read into
+    /// memory. The file must not be modified while it is being hashed.
+    #[cfg(feature = "memmap2")]
+    pub fn new_leaf_mmap(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // Mapping an empty file fails on some platforms
+        if file.metadata()?.len() == 0 {
+            return Ok(Node::from_hash(Sha256::digest([]).to_vec()));
+        }
+        // SAFETY: the map is only read while hashing, and the caller keeps
+        // the file unmodified for that long
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        Ok(Node::from_hash(Sha256::digest(&map[..]).to_vec()))
+    }
+
+    /// Create a leaf node from an already computed hash
+    fn from_hash(hash: Vec<u8>) -> Self {
+        Node {
+            hash,
+            left: None,
+            right: None,
+        }
+    }
+
+    /// Create a new internal node with left and right children
+    fn new_parent(left: Node, right: Node, hasher: &TreeHasher) -> Self {
+        let hash = hasher.hash_children(&left.hash, &right.hash);
+
+        Node {
+            hash,
+            left: Some(Box::new(left)),
+            right: Some(Box::new(right)),
+        }
+    }
+
+    /// Get the hash stored in this node
+    pub fn hash(&self) -> &[u8] {
+        &self.hash
+    }
+
+    /// Get the left and right children, or `None` for a leaf
+    pub fn children(&self) -> Option<(&Node, &Node)> {
+        Some((self.left.as_deref()?, self.right.as_deref()?))
+    }
+
+    /// Get the height of the subtree below this node, where a leaf has
+    /// height 0
+    pub fn subtree_height(&self) -> usize {
+        match self.children() {
+            Some((left, right)) => 1 + left.subtree_height().max(right.subtree_height()),
+            None => 0,
+        }
+    }
+
+    /// Get the number of leaves in the subtree below this node
+    pub fn subtree_leaves(&self) -> usize {
+        match self.children() {
+            Some((left, right)) => left.subtree_leaves() + right.subtree_leaves(),
+            None => 1,
+        }
+    }
+
+    /// Encode the node's hash as standard, padded base64, as often used for
+    /// hashes in JSON APIs
+    pub fn to_base64(&self) -> String {
+        base64::engine::general_purpose::STANDARD.encode(&self.hash)
+    }
+
+    /// Append this node and everything below it to `out` as nested JSON
+    fn write_debug_json(&self, out: &mut String) {
+        out.push_str(&format!("{{\"hash\":\"{:x}\",\"children\":[", self));
+        if let Some((left, right)) = self.children() {
+            left.write_debug_json(out);
+            out.push(',');
+            right.write_debug_json(out);
+        }
+        out.push_str("]}");
+    }
+}
+
+/// Incrementally hashes the data of a leaf node, see
+/// `Node::new_leaf_builder`
+#[derive(Debug, Clone)]
+pub struct LeafHasher {
+    state: Sha256,
+}
+
+impl LeafHasher {
+    /// Feed the next piece of leaf data
+    pub fn update(&mut self, data: &[u8]) {
+        Digest::update(&mut self.state, data);
+    }
+
+    /// Finish hashing and return the leaf node
+    pub fn finish(self) -> Node {
+        Node::from_hash(self.state.finalize().to_vec())
+    }
+}
+
+impl digest::Update for LeafHasher {
+    fn update(&mut self, data: &[u8]) {
+        LeafHasher::update(self, data);
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(self, f)
+    }
+}
+
+impl fmt::LowerHex for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hash_hex = self.hash.iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+
+        write!(f, "{}", hash_hex)
+    }
+}
+
+impl fmt::UpperHex for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hash_hex = self.hash.iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<String>();
+
+        write!(f, "{}", hash_hex)
+    }
+}
+
+/// A Merkle Tree data structure
+///
+/// Each leaf can carry metadata of type `M` which is not hashed, so it never
+/// affects the root (see `with_metadata`).
+///
+/// The tree is `Send` and `Sync` whenever `M` is, so it can be shared across
+/// threads behind an `Arc` or a lock.
+#[derive(Debug)]
+pub struct MerkleTree<M = ()> {
+    root: Option<Node>,
+    leaf_count: usize,
+    hasher: TreeHasher,
+    max_proof_len: usize,
+    observers: RootObservers,
+    /// Hex encoding of the root, filled on first use and emptied by
+    /// `set_root`, so an empty cell marks the cache dirty
+    root_hex: OnceLock<String>,
+    /// Caller data for each leaf, in leaf order
+    metadata: Vec<M>,
+    /// The data blocks themselves, if the builder was asked to retain them
+    leaf_data: Option<Vec<Vec<u8>>>,
+    /// The sorted leaf indices still provable after `prune_to`, or `None`
+    /// while the tree holds every node
+    pruned_to: Option<Vec<usize>>,
+}
+
+// Keep the thread-safety promised above from being lost to a refactor
+const _: fn() = || {
+    fn assert<T: Send + Sync>() {}
+    assert::<MerkleTree>();
+    assert::<Proof>();
+};
+
+/// An empty tree with the default configuration
+impl<M> Default for MerkleTree<M> {
+    fn default() -> Self {
+        Self::build_with_metadata(Vec::new(), TreeHasher::default(), Vec::new())
+    }
+}
+
+/// The sibling hashes passed walking down to a node, top-down, each with
+/// `true` where the walk went right
+type Descent<'a> = Vec<(&'a [u8], bool)>;
+
+/// A callback registered with `MerkleTree::on_root_change`
+type RootCallback = Box<dyn FnMut(&[u8]) + Send + Sync>;
+
+/// Callbacks registered with `MerkleTree::on_root_change`
+#[derive(Default)]
+struct RootObservers(Vec<RootCallback>);
+
+impl fmt::Debug for RootObservers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RootObservers({})", self.0.len())
+    }
+}
+
+impl MerkleTree {
+    /// Create a new Merkle Tree from a list of data blocks
+    ///
+    /// Nodes are paired left to right on each level. When a level has an odd
+    /// number of nodes, the last one is promoted to the next level unchanged
+    /// rather than being paired with a copy of itself. This can compound over
+    /// several levels, e.g. 11 leaves pair up as 11 -> 6 -> 3 -> 2 -> 1 with
+    /// a promotion on the first and third levels.
+    pub fn new(data_blocks: Vec<Vec<u8>>) -> Self {
+        Self::with_hasher(&data_blocks, TreeHasher::default())
+    }
+
+    /// Create a new Merkle Tree from data blocks passed through
+    /// `leaf_transform` before hashing, e.g. to length-prefix them
+    ///
+    /// Proofs from the tree verify with `verify_with_leaf_transform` and the
+    /// same transform. The tree does not keep the transform: data given to
+    /// later mutations such as `push` is hashed as it is.
+    pub fn new_with_leaf_transform(
+        data_blocks: Vec<Vec<u8>>,
+        leaf_transform: impl Fn(&[u8]) -> Vec<u8>,
+    ) -> Self {
+        let transformed: Vec<Vec<u8>> = data_blocks.iter()
+            .map(|data| leaf_transform(data))
+            .collect();
+        Self::with_hasher(&transformed, TreeHasher::default())
+    }
+
+    /// Create a new Merkle Tree from data blocks each passed through
+    /// `leaf_transform` with its index before hashing, for leaves that need
+    /// encoding differently depending on their position
+    ///
+    /// Proofs from the tree verify with `verify_with_indexed_transform` and
+    /// the same transform. As with `new_with_leaf_transform`, the tree does
+    /// not keep the transform.
+    pub fn new_with_indexed_transform(
+        data_blocks: Vec<Vec<u8>>,
+        leaf_transform: &dyn Fn(usize, &[u8]) -> Vec<u8>,
+    ) -> Self {
+        let transformed: Vec<Vec<u8>> = data_blocks.iter()
+            .enumerate()
+            .map(|(index, data)| leaf_transform(index, data))
+            .collect();
+        Self::with_hasher(&transformed, TreeHasher::default())
+    }
+
+    /// Create a new Merkle Tree from string data blocks, hashing the bytes of
+    /// each string directly
+    pub fn from_strs(data_blocks: &[&str]) -> Self {
+        Self::with_hasher(data_blocks, TreeHasher::default())
+    }
+
+    /// Create a new Merkle Tree from borrowed data blocks, e.g. `&[u8]`
+    /// slices or `Cow<[u8]>`, without first copying them into owned vectors
+    ///
+    /// The blocks are only read while their leaves are hashed.
+    pub fn from_borrowed<T: AsRef<[u8]>>(data_blocks: &[T]) -> Self {
+        Self::with_hasher(data_blocks, TreeHasher::default())
+    }
+
+    /// Create a new Merkle Tree with one leaf per line of input
+    ///
+    /// Lines are split on `\n` with a trailing `\r` removed, and the line
+    /// ending is not part of the leaf. A trailing newline does not produce an
+    /// empty final leaf, and empty input produces an empty tree.
+    pub fn from_reader_lines<R: BufRead>(reader: R) -> io::Result<Self> {
+        let data_blocks = reader.split(b'\n')
+            .map(|line| line.map(|mut line| {
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                line
+            }))
+            .collect::<io::Result<Vec<Vec<u8>>>>()?;
+
+        Ok(Self::new(data_blocks))
+    }
+
+    /// Create a new Merkle Tree with one leaf per map entry, hashing its key
+    /// followed by its value
+    ///
+    /// A `BTreeMap` iterates in key order, so the root depends only on the
+    /// entries and not on the order they were inserted. The boundary between
+    /// key and value is not encoded: keys should have a fixed length, or not
+    /// be a prefix of one another, for distinct entries to hash differently.
+    pub fn from_map<K: AsRef<[u8]>, V: AsRef<[u8]>>(map: &BTreeMap<K, V>) -> Self {
+        let hasher = TreeHasher::default();
+        let nodes = map.iter()
+            .map(|(key, value)| Node::from_hash(hasher.leaf.hash(&[key.as_ref(), value.as_ref()])))
+            .collect();
+
+        Self::build(nodes, hasher)
+    }
+
+    /// Create a new Merkle Tree hashing both leaves and internal nodes with
+    /// BLAKE3, which is considerably faster than SHA-256 for large trees
+    #[cfg(feature = "blake3")]
+    pub fn new_blake3(data_blocks: Vec<Vec<u8>>) -> Self {
+        let hasher = TreeHasher {
+            leaf: HashAlgorithm::Blake3,
+            node: HashAlgorithm::Blake3,
+            ..TreeHasher::default()
+        };
+        Self::with_hasher(&data_blocks, hasher)
+    }
+
+    /// Create a new Merkle Tree from a rayon parallel iterator of data
+    /// blocks, hashing the leaves in parallel
+    ///
+    /// The leaves keep the iterator's order, so the root is the same as
+    /// building sequentially over the same blocks.
+    #[cfg(feature = "rayon")]
+    pub fn from_parallel_iterator<I>(data_blocks: I) -> Self
+    where
+        I: rayon::iter::ParallelIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        use rayon::iter::ParallelIterator;
+
+        let hasher = TreeHasher::default();
+        let nodes = data_blocks.map(|data| Node::new_leaf(data.as_ref(), &hasher)).collect();
+        Self::build(nodes, hasher)
+    }
+
+    /// Create a new Merkle Tree from an async stream of data blocks
+    ///
+    /// Each block is hashed as soon as it arrives, so only the leaf hashes
+    /// are held until the stream ends and the tree is built.
+    #[cfg(feature = "futures")]
+    pub async fn from_stream<S: futures::Stream<Item = Vec<u8>>>(data_blocks: S) -> Self {
+        use futures::StreamExt;
+
+        let hasher = TreeHasher::default();
+        let mut data_blocks = std::pin::pin!(data_blocks);
+        let mut nodes = Vec::new();
+        while let Some(data) = data_blocks.next().await {
+            nodes.push(Node::new_leaf(&data, &hasher));
+        }
+        Self::build(nodes, hasher)
+    }
+
+    /// Create a new Merkle Tree over a file split into `chunk_size`-byte
+    /// leaves; the last leaf holds whatever is left and may be shorter
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn from_file_chunks(path: impl AsRef<Path>, chunk_size: usize) -> io::Result<Self> {
+        assert!(chunk_size > 0, "chunk size must be non-zero");
+
+        let mut file = File::open(path)?;
+        let mut data_blocks = Vec::new();
+        loop {
+            let mut chunk = Vec::with_capacity(chunk_size);
+            (&mut file).take(chunk_size as u64).read_to_end(&mut chunk)?;
+            if chunk.is_empty() {
+                break;
+            }
+            data_blocks.push(chunk);
+        }
+
+        Ok(Self::new(data_blocks))
+    }
+
+    /// Create a new Merkle Tree over `leaf_count` leaves, reading the data
+    /// of leaf `index` from the reader returned by `open_leaf(index)`
+    ///
+    /// Each leaf is hashed as it is read and its reader is dropped before the
+    /// next one is opened, so however large the leaves are, only a small
+    /// buffer of leaf data is held in memory at a time. Use this over `new`
+    /// when there are many large leaves, e.g. one file per leaf.
+    pub fn from_leaf_readers<R: Read>(
+        leaf_count: usize,
+        mut open_leaf: impl FnMut(usize) -> io::Result<R>,
+    ) -> io::Result<Self> {
+        let hasher = TreeHasher::default();
+        let nodes = (0..leaf_count)
+            .map(|index| hasher.hash_leaf_reader(open_leaf(index)?).map(Node::from_hash))
+            .collect::<io::Result<Vec<Node>>>()?;
+
+        Ok(Self::build(nodes, hasher))
+    }
+
+    /// Start configuring a tree, e.g. to choose its hash functions
+    pub fn builder() -> MerkleTreeBuilder {
+        MerkleTreeBuilder::new()
+    }
+
+    fn with_hasher<T: AsRef<[u8]>>(data_blocks: &[T], hasher: TreeHasher) -> Self {
+        // Convert data blocks to leaf nodes
+        let nodes: Vec<Node> = data_blocks.iter()
+            .map(|data| Node::new_leaf(data.as_ref(), &hasher))
+            .collect();
+
+        Self::build(nodes, hasher)
+    }
+
+    /// Create a new Merkle Tree whose leaves are the given hashes, used as-is
+    /// without hashing them again
+    ///
+    /// The hashes are not checked; see `try_from_hashes` for a validating
+    /// version.
+    pub fn from_hashes(leaf_hashes: Vec<Vec<u8>>) -> Self {
+        Self::new_unchecked(leaf_hashes)
+    }
+
+    /// Reconstruct a tree from leaf hashes persisted earlier, the inverse of
+    /// collecting `leaves()`
+    ///
+    /// Only the leaf hashes are stored, so the tree is rebuilt with the
+    /// default SHA-256 node hashing.
+    pub fn rebuild_from_leaves(leaves: Vec<Hash>) -> Self {
+        Self::from_hashes(leaves)
+    }
+
+    /// Create a new Merkle Tree from pre-computed SHA-256 leaf hashes,
+    /// checking that each one is `HASH_LEN` bytes long
+    pub fn try_from_hashes(leaf_hashes: Vec<Vec<u8>>) -> Result<Self, MerkleError> {
+        if let Some((index, hash)) = leaf_hashes.iter()
+            .enumerate()
+            .find(|(_, hash)| hash.len() != HASH_LEN)
+        {
+            return Err(MerkleError::InvalidLeafHashLength {
+                index,
+                len: hash.len(),
+                expected: HASH_LEN,
+            });
+        }
+
+        Ok(Self::new_unchecked(leaf_hashes))
+    }
+
+    /// Create a new Merkle Tree from pre-computed leaf hashes without any
+    /// validation, for hot paths whose input is already trusted
+    ///
+    /// The caller must ensure that:
+    /// - every hash is a SHA-256 leaf hash, i.e. `HASH_LEN` bytes produced the
+    ///   same way the default tree hashes its leaves, so proofs verify against
+    ///   `verify` and `verify_proof`;
+    /// - there are no more than `MAX_LEAVES` hashes.
+    ///
+    /// Breaking these does not cause undefined behaviour, but the resulting
+    /// root and proofs will not match a tree built with `new`.
+    pub fn new_unchecked(leaf_hashes: Vec<Vec<u8>>) -> Self {
+        let nodes: Vec<Node> = leaf_hashes.into_iter()
+            .map(Node::from_hash)
+            .collect();
+
+        Self::build(nodes, TreeHasher::default())
+    }
+
+    /// Create a top-level tree over the roots of the given sub-trees
+    ///
+    /// Each sub-tree root becomes a leaf of the new tree, so a leaf proof from a
+    /// sub-tree can be extended with a proof of its root (see `compose_proofs`).
+    /// Empty sub-trees have no root and are skipped.
+    pub fn from_subtrees<M>(subtrees: &[MerkleTree<M>]) -> Self {
+        Self::from_hashes(subtrees.iter().filter_map(|tree| tree.root_hash()).collect())
+    }
+
+    fn build(nodes: Vec<Node>, hasher: TreeHasher) -> Self {
+        let metadata = vec![(); nodes.len()];
+        Self::build_with_metadata(nodes, hasher, metadata)
+    }
+
+    /// Append a data block as a new leaf and rebuild the tree
+    pub fn push(&mut self, data: &[u8]) {
+        self.push_with_metadata(data, ());
+    }
+
+    /// Insert a data block as a new leaf at `index`, shifting later leaves
+    /// right, and rebuild the tree
+    ///
+    /// `index` may equal the leaf count, which appends. Every leaf after the
+    /// insertion point moves to a new index, so proofs generated before the
+    /// call no longer verify for those leaves (and the root changes for all).
+    pub fn insert_leaf(&mut self, index: usize, data: &[u8]) -> Result<(), MerkleError> {
+        self.insert_leaf_with_metadata(index, data, ())
+    }
+
+    /// Replace every leaf with the given data blocks and rebuild the tree,
+    /// keeping its configuration and registered callbacks
+    pub fn replace_all(&mut self, data_blocks: Vec<Vec<u8>>) {
+        let nodes = data_blocks.iter()
+            .map(|data| Node::new_leaf(data, &self.hasher))
+            .collect();
+        self.metadata = vec![(); data_blocks.len()];
+        if let Some(leaf_data) = &mut self.leaf_data {
+            *leaf_data = data_blocks;
+        }
+        self.pruned_to = None;
+        self.rebuild(nodes);
+    }
+
+    /// Combine a leaf proof from a sub-tree with a proof of that sub-tree's
+    /// root in a top-level tree built with `from_subtrees`
+    ///
+    /// The resulting proof verifies the leaf data directly against the
+    /// top-level root in a single `verify` call.
+    pub fn compose_proofs(
+        leaf_proof: &[Vec<u8>],
+        leaf_path: &[bool],
+        root_proof: &[Vec<u8>],
+        root_path: &[bool],
+    ) -> (Vec<Vec<u8>>, Vec<bool>) {
+        let proof = leaf_proof.iter().chain(root_proof).cloned().collect();
+        let proof_path = leaf_path.iter().chain(root_path).copied().collect();
+
+        (proof, proof_path)
+    }
+}
+
+impl<M> MerkleTree<M> {
+    /// Create a new Merkle Tree over data blocks that each carry metadata,
+    /// e.g. a timestamp or source id, to look up by leaf index
+    ///
+    /// Only the data blocks are hashed, so the root is the same as `new`
+    /// gives for the same data, whatever the metadata.
+    pub fn with_metadata(leaves: Vec<(Vec<u8>, M)>) -> Self {
+        let hasher = TreeHasher::default();
+        let (nodes, metadata) = leaves.into_iter()
+            .map(|(data, metadata)| (Node::new_leaf(&data, &hasher), metadata))
+            .unzip();
+        Self::build_with_metadata(nodes, hasher, metadata)
+    }
+
+    fn build_with_metadata(nodes: Vec<Node>, hasher: TreeHasher, metadata: Vec<M>) -> Self {
+        MerkleTree {
+            leaf_count: nodes.len(),
+            root: build_root(nodes, &hasher),
+            hasher,
+            max_proof_len: DEFAULT_MAX_PROOF_LEN,
+            observers: RootObservers::default(),
+            root_hex: OnceLock::new(),
+            metadata,
+            leaf_data: None,
+            pruned_to: None,
+        }
+    }
+
+    /// Drop every leaf and node, leaving an empty tree that keeps its
+    /// configuration and registered callbacks, ready to be pushed to again
+    pub fn clear(&mut self) {
+        self.metadata.clear();
+        if let Some(leaf_data) = &mut self.leaf_data {
+            leaf_data.clear();
+        }
+        self.pruned_to = None;
+        self.set_root(None, 0);
+    }
+
+    /// Get the data block of the leaf at `index`, if the tree was built to
+    /// retain it (see `MerkleTreeBuilder::retain_leaf_data`)
+    pub fn leaf_data(&self, index: usize) -> Option<&[u8]> {
+        self.leaf_data.as_ref()?.get(index).map(|data| data.as_slice())
+    }
+
+    /// Check whether `other` holds the same data blocks in the same order,
+    /// whatever hash functions either tree uses
+    ///
+    /// Compares the retained data, not hashes, so it tells whether a tree
+    /// migrated to a new hash function still commits to the same data.
+    /// Returns `false` unless both trees retain their leaf data.
+    pub fn same_leaves(&self, other: &impl MerkleStructure) -> bool {
+        self.leaf_count == other.leaf_count()
+            && (0..self.leaf_count).all(|index| {
+                matches!(
+                    (self.leaf_data(index), other.leaf_data(index)),
+                    (Some(ours), Some(theirs)) if ours == theirs
+                )
+            })
+    }
+
+    /// Get the metadata of the leaf at `index`
+    pub fn metadata(&self, index: usize) -> Option<&M> {
+        self.metadata.get(index)
+    }
+
+    /// Append a data block with its metadata as a new leaf and rebuild the
+    /// tree
+    pub fn push_with_metadata(&mut self, data: &[u8], metadata: M) {
+        let mut nodes = self.leaf_nodes();
+        nodes.push(Node::new_leaf(data, &self.hasher));
+        self.metadata.push(metadata);
+        if let Some(leaf_data) = &mut self.leaf_data {
+            leaf_data.push(data.to_vec());
+        }
+        self.rebuild(nodes);
+    }
+
+    /// Insert a data block with its metadata as a new leaf at `index`,
+    /// shifting later leaves right, and rebuild the tree (see `insert_leaf`)
+    pub fn insert_leaf_with_metadata(
+        &mut self,
+        index: usize,
+        data: &[u8],
+        metadata: M,
+    ) -> Result<(), MerkleError> {
+        if index > self.leaf_count {
+            return Err(MerkleError::IndexOutOfRange { index, leaf_count: self.leaf_count });
+        }
+        let mut nodes = self.leaf_nodes();
+        nodes.insert(index, Node::new_leaf(data, &self.hasher));
+        self.metadata.insert(index, metadata);
+        if let Some(leaf_data) = &mut self.leaf_data {
+            leaf_data.insert(index, data.to_vec());
+        }
+        self.rebuild(nodes);
+        Ok(())
+    }
+
+    /// Replace the data block of the leaf at `index` and rebuild the tree,
+    /// keeping its metadata
+    pub fn update_leaf(&mut self, index: usize, data: &[u8]) -> Result<(), MerkleError> {
+        self.check_index(index)?;
+        let mut nodes = self.leaf_nodes();
+        nodes[index] = Node::new_leaf(data, &self.hasher);
+        if let Some(leaf_data) = &mut self.leaf_data {
+            leaf_data[index] = data.to_vec();
+        }
+        self.rebuild(nodes);
+        Ok(())
+    }
+
+    /// Remove the leaf at `index`, and its metadata, shifting later leaves
+    /// left, and rebuild the tree
+    pub fn delete_leaf(&mut self, index: usize) -> Result<(), MerkleError> {
+        self.check_index(index)?;
+        let mut nodes = self.leaf_nodes();
+        nodes.remove(index);
+        self.metadata.remove(index);
+        if let Some(leaf_data) = &mut self.leaf_data {
+            leaf_data.remove(index);
+        }
+        self.rebuild(nodes);
+        Ok(())
+    }
+
+    /// Append all leaves of `subtree` after the leaves of this tree
+    ///
+    /// The result is the same as pushing each of the subtree's leaves in
+    /// turn. The subtree's leaf hashes are taken as they are, so both trees
+    /// should use the same hashing configuration. When both trees hold the
+    /// same power-of-two number of leaves, the subtree slots in whole under a
+    /// new root without rebuilding either side. Leaf data is only retained
+    /// afterwards if both trees retained it.
+    pub fn append_subtree(&mut self, mut subtree: MerkleTree<M>) {
+        self.assert_unpruned("append_subtree");
+        subtree.assert_unpruned("append_subtree");
+        let aligned = self.leaf_count == subtree.leaf_count && self.leaf_count.is_power_of_two();
+        self.metadata.extend(mem::take(&mut subtree.metadata));
+        self.leaf_data = match (self.leaf_data.take(), subtree.leaf_data.take()) {
+            (Some(mut leaf_data), Some(appended)) => {
+                leaf_data.extend(appended);
+                Some(leaf_data)
+            }
+            _ => None,
+        };
+
+        if aligned {
+            if let (Some(left), Some(right)) = (self.root.take(), subtree.root) {
+                let root = Node::new_parent(left, right, &self.hasher);
+                self.set_root(Some(root), self.leaf_count * 2);
+            }
+            return;
+        }
+
+        let mut nodes = self.leaf_nodes();
+        nodes.extend(subtree.leaf_nodes());
+        self.rebuild(nodes);
+    }
+
+    /// Register a callback to be told the new root hash after a mutation
+    ///
+    /// The callback fires once per `push`, `insert_leaf`, `update_leaf`,
+    /// `delete_leaf`, `replace_all` or `append_subtree` call, after the root
+    /// has been recomputed. It does not fire when a mutation leaves the tree
+    /// empty, since there is no root to report.
+    pub fn on_root_change(&mut self, callback: impl FnMut(&[u8]) + Send + Sync + 'static) {
+        self.observers.0.push(Box::new(callback));
+    }
+
+    /// Discard every node not needed to prove the leaves at `indices`
+    ///
+    /// The nodes on the paths from those leaves to the root are kept along
+    /// with the hashes of their siblings; every other subtree is reduced to
+    /// its root hash. The tree's root is unchanged and it still verifies
+    /// proofs, but `generate_proof` returns `None` for any other leaf.
+    ///
+    /// Pruning is meant for trees that only serve proofs from then on:
+    /// anything that needs every leaf (mutations other than `replace_all`,
+    /// `leaves`, `freeze`, `multiproof`, `to_bytes`) panics on a pruned
+    /// tree. Pruning an already pruned tree can only narrow it further.
+    pub fn prune_to(&mut self, indices: &[usize]) -> Result<(), MerkleError> {
+        for &index in indices {
+            self.check_index(index)?;
+        }
+        let mut kept: Vec<usize> = indices.iter()
+            .copied()
+            .filter(|&index| self.is_provable(index))
+            .collect();
+        kept.sort_unstable();
+        kept.dedup();
+
+        if let Some(root) = &mut self.root {
+            prune_node(root, 0, self.leaf_count, &kept);
+        }
+        self.pruned_to = Some(kept);
+        Ok(())
+    }
+
+    /// Whether `generate_proof` can still prove the leaf at `index`
+    fn is_provable(&self, index: usize) -> bool {
+        match &self.pruned_to {
+            Some(kept) => kept.binary_search(&index).is_ok(),
+            None => index < self.leaf_count,
+        }
+    }
+
+    fn assert_unpruned(&self, operation: &str) {
+        assert!(
+            self.pruned_to.is_none(),
+            "{} needs every leaf, but the tree has been pruned",
+            operation
+        );
+    }
+
+    /// Release excess capacity held by the tree's buffers
+    ///
+    /// The per-leaf metadata and retained data grow like any `Vec` through
+    /// `push` and keep their capacity through `delete_leaf`, and a hash
+    /// handed over by the caller may keep the spare capacity of the vector
+    /// it came in. All of them are trimmed, along with the callback list and
+    /// the pruned leaf list; the tree's structure and root are unchanged.
+    pub fn compact(&mut self) {
+        let mut stack: Vec<&mut Node> = self.root.iter_mut().collect();
+        while let Some(Node { hash, left, right }) = stack.pop() {
+            hash.shrink_to_fit();
+            stack.extend(left.as_deref_mut());
+            stack.extend(right.as_deref_mut());
+        }
+        self.metadata.shrink_to_fit();
+        if let Some(leaf_data) = &mut self.leaf_data {
+            leaf_data.iter_mut().for_each(Vec::shrink_to_fit);
+            leaf_data.shrink_to_fit();
+        }
+        if let Some(kept) = &mut self.pruned_to {
+            kept.shrink_to_fit();
+        }
+        self.observers.0.shrink_to_fit();
+    }
+
+    fn check_index(&self, index: usize) -> Result<(), MerkleError> {
+        if index >= self.leaf_count {
+            return Err(MerkleError::IndexOutOfRange { index, leaf_count: self.leaf_count });
+        }
+        Ok(())
+    }
+
+    /// Copy out the current leaves so the tree can be rebuilt from them
+    fn leaf_nodes(&self) -> Vec<Node> {
+        self.leaves().map(|hash| Node::from_hash(hash.to_vec())).collect()
+    }
+
+    /// Rebuild the tree from new leaves, keeping its configuration
+    fn rebuild(&mut self, nodes: Vec<Node>) {
+        let leaf_count = nodes.len();
+        self.set_root(build_root(nodes, &self.hasher), leaf_count);
+    }
+
+    /// Install a new root, marking everything cached from the old one dirty
+    /// and telling the registered callbacks about it
+    ///
+    /// Every mutation must go through here so no query sees a stale root.
+    fn set_root(&mut self, root: Option<Node>, leaf_count: usize) {
+        self.root = root;
+        self.leaf_count = leaf_count;
+        self.root_hex = OnceLock::new();
+        if let Some(root) = &self.root {
+            for callback in self.observers.0.iter_mut() {
+                callback(&root.hash);
+            }
+        }
+    }
+
+    /// Get the root node, e.g. to inspect the shape of the tree
+    pub fn root_node(&self) -> Option<&Node> {
+        self.root.as_ref()
+    }
+
+    /// Dump the whole tree as nested JSON for debugging, each node giving
+    /// its hex hash and its children (none for a leaf), e.g.
+    /// `{"leaf_count":1,"root":{"hash":"ca97...","children":[]}}`
+    ///
+    /// The root is `null` for an empty tree. Nodes removed by `prune_to`
+    /// show up as leaves.
+    pub fn to_debug_json(&self) -> String {
+        let mut json = format!("{{\"leaf_count\":{},\"root\":", self.leaf_count);
+        match &self.root {
+            Some(root) => root.write_debug_json(&mut json),
+            None => json.push_str("null"),
+        }
+        json.push('}');
+        json
+    }
+
+    /// Get the root hash of the Merkle Tree
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        self.root.as_ref().map(|node| node.hash.clone())
+    }
+
+    /// Borrow the root hash of the Merkle Tree, avoiding the copy
+    /// `root_hash` makes
+    pub fn root(&self) -> Option<&[u8]> {
+        self.root.as_ref().map(|node| node.hash.as_slice())
+    }
+
+    /// Get the root hash of the Merkle Tree as a lowercase hex string
+    ///
+    /// The encoding is computed once and cached until the tree is mutated.
+    pub fn root_hex(&self) -> Option<&str> {
+        let root = self.root.as_ref()?;
+        Some(self.root_hex.get_or_init(|| hex::encode(&root.hash)))
+    }
+
+    /// Get the root hash as a hex string with its bytes reversed, the way
+    /// Bitcoin displays hashes in block explorers
+    ///
+    /// Only the display changes; the tree hashes in natural byte order.
+    pub fn root_hex_reversed(&self) -> Option<String> {
+        self.root.as_ref().map(|node| {
+            let reversed: Vec<u8> = node.hash.iter().rev().copied().collect();
+            hex::encode(reversed)
+        })
+    }
+
+    /// Get the number of leaves in the tree
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Whether the tree is a perfect binary tree, with a power-of-two number
+    /// of leaves and so no promoted nodes (an empty tree is not)
+    pub fn is_perfect(&self) -> bool {
+        self.leaf_count.is_power_of_two()
+    }
+
+    /// Get structural metrics describing the shape of the tree
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats {
+            leaf_count: self.leaf_count,
+            height: 0,
+            node_count: self.leaf_count,
+            promoted_nodes: 0,
+            is_perfect: self.is_perfect(),
+        };
+
+        let mut width = self.leaf_count;
+        while width > 1 {
+            // Each pair gains a parent; a promoted node is not a new node
+            stats.node_count += width / 2;
+            stats.promoted_nodes += width % 2;
+            width = width.div_ceil(2);
+            stats.height += 1;
+        }
+
+        stats
+    }
+
+    /// Iterate over the leaf hashes in insertion order
+    ///
+    /// Panics if the tree has been pruned with `prune_to`.
+    pub fn leaves(&self) -> Leaves<'_> {
+        self.assert_unpruned("leaves");
+        Leaves { stack: self.root.iter().collect() }
+    }
+
+    /// Get the hash of every node in the tree, leaves and internal nodes,
+    /// with duplicates removed
+    ///
+    /// Hashes are listed in depth-first order starting from the root, which
+    /// suits persisting the tree in a store keyed by hash.
+    pub fn all_node_hashes(&self) -> Vec<Hash> {
+        let mut seen = std::collections::HashSet::new();
+        let mut hashes = Vec::new();
+        let mut stack: Vec<&Node> = self.root.iter().collect();
+
+        while let Some(node) = stack.pop() {
+            if seen.insert(&node.hash) {
+                hashes.push(node.hash.clone());
+            }
+            stack.extend(node.right.as_deref());
+            stack.extend(node.left.as_deref());
+        }
+        hashes
+    }
+
+    /// Generate an inclusion proof for the leaf at `index`
+    ///
+    /// Returns the sibling hashes from the leaf up to the root together with the
+    /// proof path expected by `verify`. Levels where the leaf's ancestor was
+    /// promoted without a sibling contribute nothing to the proof. With the
+    /// `tracing` feature this runs in a `generate_proof` span.
+    pub fn generate_proof(&self, index: usize) -> Option<(Vec<Vec<u8>>, Vec<bool>)> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("generate_proof", index).entered();
+
+        let root = self.root.as_ref()?;
+        if !self.is_provable(index) {
+            return None;
+        }
+
+        let proof_path = proof_path_for(index, self.leaf_count);
+
+        // Walk down from the root collecting the siblings along the way
+        let mut proof = Vec::with_capacity(proof_path.len());
+        let mut node = root;
+        for &is_right in proof_path.iter().rev() {
+            let left = node.left.as_deref()?;
+            let right = node.right.as_deref()?;
+            if is_right {
+                proof.push(left.hash.clone());
+                node = right;
+            } else {
+                proof.push(right.hash.clone());
+                node = left;
+            }
+        }
+        proof.reverse();
+
+        Some((proof, proof_path))
+    }
+
+    /// Get, for each level from the leaves up, the position within that
+    /// level of the node paired with the ancestor of the leaf at
+    /// `leaf_index`, or `None` where the ancestor is promoted unpaired
+    ///
+    /// The `Some` entries are the nodes whose hashes make up the leaf's
+    /// proof. Returns an empty list for an index outside the tree.
+    pub fn sibling_indices(&self, leaf_index: usize) -> Vec<Option<usize>> {
+        if leaf_index >= self.leaf_count {
+            return Vec::new();
+        }
+
+        let mut siblings = Vec::new();
+        let (mut position, mut width) = (leaf_index, self.leaf_count);
+        while width > 1 {
+            let sibling = position ^ 1;
+            siblings.push((sibling < width).then_some(sibling));
+            position /= 2;
+            width = width.div_ceil(2);
+        }
+        siblings
+    }
+
+    /// Get the level, counting the leaves as level 0, of the lowest node that
+    /// both leaf `a` and leaf `b` sit under
+    ///
+    /// A leaf shares level 0 with itself. Returns `None` if either index is
+    /// outside the tree.
+    pub fn common_ancestor_level(&self, a: usize, b: usize) -> Option<usize> {
+        if a >= self.leaf_count || b >= self.leaf_count {
+            return None;
+        }
+        Some((usize::BITS - (a ^ b).leading_zeros()) as usize)
+    }
+
+    /// Generate a proof for the leaf at `index` padded to exactly `depth`
+    /// siblings, for verifiers such as zk circuits that only take proofs of
+    /// one fixed depth
+    ///
+    /// The real siblings come first. Each padding entry above them is a
+    /// `PADDING_SIBLING` with path `Side::Left`, that is with the running
+    /// hash on the left and the padding on the right, which
+    /// `verify_padded_proof` passes over without hashing. Returns `None` if
+    /// the leaf is not provable or its proof is longer than `depth`.
+    pub fn generate_padded_proof(&self, index: usize, depth: usize) -> Option<Proof> {
+        let (mut siblings, mut path) = self.generate_proof(index)?;
+        if siblings.len() > depth {
+            return None;
+        }
+        siblings.resize(depth, PADDING_SIBLING.to_vec());
+        path.resize(depth, false);
+        Some(Proof::new(index, siblings, &path))
+    }
+
+    /// Check the leaf at `index` against the tree itself, without a
+    /// caller-supplied path
+    ///
+    /// The path is found by walking down the actual nodes, counting the
+    /// leaves under each left child, and the root is rebuilt along it. Fails
+    /// if that disagrees with the stored hashes, with retained leaf data or
+    /// with what `generate_proof` returns.
+    #[cfg(test)]
+    pub(crate) fn verify_internal(&self, index: usize) -> bool {
+        let Some(root) = &self.root else {
+            return false;
+        };
+        if index >= self.leaf_count {
+            return false;
+        }
+
+        let mut proof = Vec::new();
+        let mut proof_path = Vec::new();
+        let (mut node, mut offset) = (root, index);
+        while let Some((left, right)) = node.children() {
+            let left_leaves = left.subtree_leaves();
+            let is_right = offset >= left_leaves;
+            if is_right {
+                proof.push(left.hash.clone());
+                offset -= left_leaves;
+                node = right;
+            } else {
+                proof.push(right.hash.clone());
+                node = left;
+            }
+            proof_path.push(is_right);
+        }
+        proof.reverse();
+        proof_path.reverse();
+
+        let data_matches = self.leaf_data(index)
+            .is_none_or(|data| self.hasher.hash_leaf(data) == node.hash);
+        offset == 0
+            && data_matches
+            && self.hasher.root_from_proof(node.hash.clone(), &proof, &proof_path) == root.hash
+            && self.generate_proof(index) == Some((proof, proof_path))
+    }
+
+    /// Convert into an immutable, compact tree for serving proofs
+    ///
+    /// The frozen tree keeps every level's hashes in one contiguous array
+    /// instead of boxed nodes, and produces the same proofs as this tree, but
+    /// can no longer be mutated.
+    pub fn freeze(self) -> FrozenMerkleTree {
+        self.assert_unpruned("freeze");
+        let mut levels = Vec::new();
+        let mut width = self.leaf_count;
+        while width > 0 {
+            levels.push(width);
+            if width == 1 {
+                break;
+            }
+            width = width.div_ceil(2);
+        }
+
+        let hashes = levels.iter()
+            .flat_map(|&width| (0..width).map(move |position| (width, position)))
+            .map(|(width, position)| {
+                self.node_at(width, position).expect("position is within its level").hash.clone()
+            })
+            .collect();
+
+        FrozenMerkleTree::new(hashes, levels, self.hasher, self.max_proof_len)
+    }
+
+    /// Generate an inclusion proof for the leaf at `index` as a standalone
+    /// `Proof`, which records the leaf index alongside the siblings and path
+    pub fn proof(&self, index: usize) -> Option<Proof> {
+        let (siblings, proof_path) = self.generate_proof(index)?;
+        Some(Proof::new(index, siblings, &proof_path))
+    }
+
+    /// Find the node at `position` on the level of the tree that is
+    /// `level_width` nodes wide
+    ///
+    /// Everything above a level is shaped like a tree built over that many
+    /// leaves, so the node is reached by following the proof path its
+    /// position would have in such a tree.
+    fn node_at(&self, level_width: usize, position: usize) -> Option<&Node> {
+        let mut node = self.root.as_ref()?;
+        for &is_right in proof_path_for(position, level_width).iter().rev() {
+            node = if is_right { node.right.as_deref()? } else { node.left.as_deref()? };
+        }
+        Some(node)
+    }
+
+    /// Get the root hash of the subtree over the leaves `start..end`
+    ///
+    /// The range must be exactly the leaves under one node, e.g. an aligned
+    /// power-of-two block such as `0..4` or `4..8`, or the promoted tail
+    /// `4..5` of a 5-leaf tree. Returns `None` for any other range, or if
+    /// the node has been pruned.
+    pub fn subtree_root(&self, start: usize, end: usize) -> Option<Hash> {
+        let (node, _) = self.descend_to(start, end)?;
+        Some(node.hash.clone())
+    }
+
+    /// Get the root hash of the subtree over the leaves `start..end` with a
+    /// proof that it is part of this tree
+    ///
+    /// One proof covers the whole range, ending at the node over it rather
+    /// than a leaf, so it takes `verify_subtree_inclusion` rather than
+    /// `verify`; the leaves themselves are checked against the subtree root.
+    /// The proof's `leaf_index` is `start`. Accepts the same ranges as
+    /// `subtree_root`.
+    pub fn subtree_inclusion_proof(&self, start: usize, end: usize) -> Option<(Hash, Proof)> {
+        let (node, siblings) = self.descend_to(start, end)?;
+        let (siblings, proof_path): (Vec<Hash>, Vec<bool>) = siblings.into_iter()
+            .rev()
+            .map(|(sibling, is_right)| (sibling.to_vec(), is_right))
+            .unzip();
+        Some((node.hash.clone(), Proof::new(start, siblings, &proof_path)))
+    }
+
+    /// Walk down from the root to the node over the leaves `start..end`,
+    /// collecting the siblings passed on the way
+    fn descend_to(&self, start: usize, end: usize) -> Option<(&Node, Descent<'_>)> {
+        let mut node = self.root.as_ref()?;
+        let (mut node_start, mut node_end) = (0, self.leaf_count);
+        let mut siblings = Vec::new();
+        while (node_start, node_end) != (start, end) {
+            let (left, right) = node.children()?;
+            let mid = node_start.checked_add(checked_split(node_end - node_start)?)?;
+            if end <= mid {
+                siblings.push((right.hash.as_slice(), false));
+                (node, node_end) = (left, mid);
+            } else if start >= mid {
+                siblings.push((left.hash.as_slice(), true));
+                (node, node_start) = (right, mid);
+            } else {
+                return None;
+            }
+        }
+        Some((node, siblings))
+    }
+
+    /// Verify a proof from `subtree_inclusion_proof` that `subtree_root` is
+    /// a node of this tree
+    pub fn verify_subtree_inclusion(&self, subtree_root: &[u8], proof: &Proof) -> bool {
+        if proof.siblings.len() != proof.path.len() || proof.siblings.len() > self.max_proof_len {
+            return false;
+        }
+        self.reconstructs_root(subtree_root.to_vec(), &proof.siblings, &proof.path_bits())
+    }
+
+    /// Copy out the subtree under the node reached by following `node_path`
+    /// down from the root, as a tree of its own
+    ///
+    /// Proofs from the copy verify against the node's hash, and are the
+    /// bottom part of the corresponding proofs in this tree. The copy keeps
+    /// the hashing configuration and any retained leaf data, but not
+    /// metadata or callbacks. Returns `None` if the path leads below a leaf.
+    /// Panics if the tree has been pruned.
+    pub fn subtree(&self, node_path: &[Side]) -> Option<MerkleTree> {
+        self.assert_unpruned("subtree");
+        let mut node = self.root.as_ref()?;
+        let (mut start, mut width) = (0usize, self.leaf_count);
+        for side in node_path {
+            let (left, right) = node.children()?;
+            let split = checked_split(width)?;
+            match side {
+                Side::Left => (node, width) = (left, split),
+                Side::Right => {
+                    (node, start, width) = (right, start.checked_add(split)?, width - split)
+                }
+            }
+        }
+
+        Some(MerkleTree {
+            root: Some(node.clone()),
+            leaf_count: width,
+            hasher: self.hasher.clone(),
+            max_proof_len: self.max_proof_len,
+            observers: RootObservers::default(),
+            root_hex: OnceLock::new(),
+            metadata: vec![(); width],
+            leaf_data: self.leaf_data.as_ref().map(|data| data[start..start + width].to_vec()),
+            pruned_to: None,
+        })
+    }
+
+    /// Generate an inclusion proof for the leftmost leaf
+    ///
+    /// The leftmost leaf is always a left child, so every entry of its path
+    /// is `false` and the proof has one sibling per level of the tree.
+    pub fn first_proof(&self) -> Option<(Vec<Vec<u8>>, Vec<bool>)> {
+        self.generate_proof(0)
+    }
+
+    /// Generate an inclusion proof for the rightmost leaf
+    ///
+    /// Every entry of its path is `true`, but levels where the rightmost node
+    /// was promoted have no sibling, so for leaf counts that are not powers
+    /// of two the proof is shorter than the tree is high. For 5 leaves the
+    /// last leaf is promoted twice and its proof holds a single sibling, the
+    /// root of the first four leaves.
+    pub fn last_proof(&self) -> Option<(Vec<Vec<u8>>, Vec<bool>)> {
+        self.generate_proof(self.leaf_count.checked_sub(1)?)
+    }
+
+    /// Generate an audit proof for the leaf at `index`
+    ///
+    /// "Audit proof" is Certificate Transparency's name for an inclusion
+    /// proof; this is the same as `generate_proof`.
+    pub fn audit_proof(&self, index: usize) -> Option<(Vec<Vec<u8>>, Vec<bool>)> {
+        self.generate_proof(index)
+    }
+
+    /// Verify an audit proof (Certificate Transparency's name for an
+    /// inclusion proof); this is the same as `verify`
+    pub fn verify_audit_proof(&self, data: &[u8], proof: &[Vec<u8>], proof_path: &[bool]) -> bool {
+        self.verify(data, proof, proof_path)
+    }
+
+    /// Generate an inclusion proof for the leaf at `index` with each sibling
+    /// hash encoded as a lowercase hex string
+    pub fn proof_hex(&self, index: usize) -> Option<(Vec<String>, Vec<bool>)> {
+        let (proof, proof_path) = self.generate_proof(index)?;
+
+        Some((proof.iter().map(hex::encode).collect(), proof_path))
+    }
+
+    /// Verify that a data block is part of the tree by providing a proof
+    pub fn verify(&self, data: &[u8], proof: &[Vec<u8>], proof_path: &[bool]) -> bool {
+        self.try_verify(data, proof, proof_path).unwrap_or(false)
+    }
+
+    /// Verify a proof whose path follows the given convention, e.g. one made
+    /// by an implementation that records the sibling's side
+    pub fn verify_with_convention(
+        &self,
+        data: &[u8],
+        proof: &[Vec<u8>],
+        proof_path: &[bool],
+        convention: PathConvention,
+    ) -> bool {
+        self.verify(data, proof, &convention.normalize(proof_path))
+    }
+
+    /// Verify that a data block is part of a tree whose leaves were passed
+    /// through `leaf_transform` before hashing (see `new_with_leaf_transform`)
+    pub fn verify_with_leaf_transform(
+        &self,
+        data: &[u8],
+        proof: &[Vec<u8>],
+        proof_path: &[bool],
+        leaf_transform: impl Fn(&[u8]) -> Vec<u8>,
+    ) -> bool {
+        self.verify(&leaf_transform(data), proof, proof_path)
+    }
+
+    /// Verify that a data block is the leaf at `index` of a tree built with
+    /// `new_with_indexed_transform`, transforming it as the leaf at `index`
+    ///
+    /// The proof must be for `index` itself, as with `verify_with_index`, so
+    /// data cannot be passed off under another position's encoding.
+    pub fn verify_with_indexed_transform(
+        &self,
+        index: usize,
+        data: &[u8],
+        proof: &[Vec<u8>],
+        proof_path: &[bool],
+        leaf_transform: &dyn Fn(usize, &[u8]) -> Vec<u8>,
+    ) -> bool {
+        self.verify_with_index(index, &leaf_transform(index, data), proof, proof_path)
+    }
+
+    /// Verify that a data block is part of the tree doing the same work for
+    /// any proof, so the timing reveals neither where a mismatch occurs nor
+    /// how long the proof is
+    ///
+    /// Proofs longer than the maximum proof length, which is public, are
+    /// rejected before any hashing. Otherwise every sibling is hashed, dummy
+    /// levels pad the work up to the maximum proof length, the root is
+    /// compared in constant time, and malformed proofs are only rejected at
+    /// the end. This always costs `max_proof_len` node hashes (64 by
+    /// default) where `verify` needs one per level, so about 3x the work for
+    /// a million-leaf tree.
+    pub fn verify_constant_time(
+        &self,
+        data: &[u8],
+        proof: &[Vec<u8>],
+        proof_path: &[bool],
+    ) -> bool {
+        if proof.len() > self.max_proof_len {
+            return false;
+        }
+
+        let mut current_hash = self.hasher.hash_leaf(data);
+        for (level, sibling) in proof.iter().enumerate() {
+            let is_right = proof_path.get(level).copied().unwrap_or(false);
+            current_hash = if is_right {
+                self.hasher.hash_children(sibling, &current_hash)
+            } else {
+                self.hasher.hash_children(&current_hash, sibling)
+            };
+        }
+        let mut padding = current_hash.clone();
+        for _ in proof.len()..self.max_proof_len {
+            padding = self.hasher.hash_children(&padding, &padding);
+        }
+        std::hint::black_box(padding);
+
+        let root = self.root.as_ref().map_or(&[][..], |root| root.hash.as_slice());
+        let well_formed = self.check_proof(proof, proof_path).is_ok();
+        well_formed & bool::from(current_hash.ct_eq(root))
+    }
+
+    /// Verify that a data block is part of the tree, reporting malformed
+    /// proofs as errors instead of a plain mismatch
+    ///
+    /// Proofs longer than the tree's maximum proof length are rejected before
+    /// any hashing is done, which bounds the cost of verifying hostile input,
+    /// and so are paths that run out before reaching a leaf of a tree this
+    /// size, and siblings that are neither a leaf nor a node hash long.
+    pub fn try_verify(
+        &self,
+        data: &[u8],
+        proof: &[Vec<u8>],
+        proof_path: &[bool],
+    ) -> Result<bool, MerkleError> {
+        self.check_proof(proof, proof_path)?;
+
+        // Calculate the leaf node hash
+        Ok(self.reconstructs_root(self.hasher.hash_leaf(data), proof, proof_path))
+    }
+
+    /// Verify that a data block is part of the tree, also returning how many
+    /// hash function calls verification made
+    ///
+    /// A proof that gets as far as hashing costs one call for the leaf and
+    /// one per sibling, e.g. to estimate the gas an on-chain verifier would
+    /// use. Proofs rejected by the structural checks of `try_verify` cost
+    /// none.
+    pub fn verify_counted(
+        &self,
+        data: &[u8],
+        proof: &[Vec<u8>],
+        proof_path: &[bool],
+    ) -> (bool, usize) {
+        match self.try_verify(data, proof, proof_path) {
+            Ok(valid) => (valid, proof.len() + 1),
+            Err(_) => (false, 0),
+        }
+    }
+
+    /// Verify that a leaf hash is part of the tree by providing a proof
+    ///
+    /// Unlike `verify`, the caller supplies the leaf hash itself rather than
+    /// the data block, so the preimage never has to be revealed.
+    pub fn verify_leaf_hash(
+        &self,
+        leaf_hash: &[u8],
+        proof: &[Vec<u8>],
+        proof_path: &[bool],
+    ) -> bool {
+        self.check_proof(proof, proof_path).is_ok()
+            && self.reconstructs_root(leaf_hash.to_vec(), proof, proof_path)
+    }
+
+    /// Verify that a data block is part of the tree and return the index of
+    /// the leaf the proof is for
+    ///
+    /// The path alone fixes the leaf's position in a tree of this size.
+    /// Returns `None` if the proof does not verify, or if its path does not
+    /// lead to a leaf of this tree.
+    pub fn verify_and_locate(
+        &self,
+        data: &[u8],
+        proof: &[Vec<u8>],
+        proof_path: &[bool],
+    ) -> Option<usize> {
+        let index = leaf_index_for_path(proof_path, self.leaf_count)?;
+        self.verify(data, proof, proof_path).then_some(index)
+    }
+
+    /// Verify that a data block is the leaf at `index`, not just some leaf
+    /// of the tree
+    ///
+    /// When the same data appears at several positions, `verify` accepts a
+    /// proof for any of them; this also requires the path to lead to `index`.
+    pub fn verify_with_index(
+        &self,
+        index: usize,
+        data: &[u8],
+        proof: &[Vec<u8>],
+        proof_path: &[bool],
+    ) -> bool {
+        self.verify_and_locate(data, proof, proof_path) == Some(index)
+    }
+
+    /// Verify that a data block is part of the tree, explaining why not if
+    /// verification fails
+    pub fn verify_detailed(
+        &self,
+        data: &[u8],
+        proof: &[Vec<u8>],
+        proof_path: &[bool],
+    ) -> VerifyOutcome {
+        let Some(root) = &self.root else {
+            return VerifyOutcome::EmptyTree;
+        };
+        if let Err(error) = self.check_proof(proof, proof_path) {
+            return match error {
+                MerkleError::PathLengthMismatch { proof_len, path_len } => {
+                    VerifyOutcome::LengthMismatch { proof_len, path_len }
+                }
+                MerkleError::ProofTooLong { len, max } => VerifyOutcome::ProofTooLong { len, max },
+                MerkleError::InvalidSiblingLength { index, len, expected } => {
+                    VerifyOutcome::InvalidSiblingLength { index, len, expected }
+                }
+                MerkleError::ImpossiblePath { .. } => VerifyOutcome::ImpossiblePath,
+                error => unreachable!("check_proof does not report {:?}", error),
+            };
+        }
+
+        let computed = self.hasher.root_from_proof(self.hasher.hash_leaf(data), proof, proof_path);
+        if computed == root.hash {
+            VerifyOutcome::Valid
+        } else {
+            VerifyOutcome::RootMismatch { computed, expected: root.hash.clone() }
+        }
+    }
+
+    /// Get the longest proof this tree will attempt to verify
+    pub fn max_proof_len(&self) -> usize {
+        self.max_proof_len
+    }
+
+    /// Reject proofs that cannot be verified against this tree
+    fn check_proof(&self, proof: &[Vec<u8>], proof_path: &[bool]) -> Result<(), MerkleError> {
+        if self.root.is_none() {
+            return Err(MerkleError::EmptyTree);
+        }
+        let leaf_len = self.leaf_hash_len().unwrap_or(self.hasher.leaf.output_len());
+        check_proof_shape(
+            proof,
+            proof_path,
+            self.leaf_count,
+            self.max_proof_len,
+            (leaf_len, self.hasher.node.output_len()),
+        )
+    }
+
+    /// The length of the tree's leaf hashes, which for a tree built with
+    /// `from_hashes` need not be the leaf hasher's output length
+    ///
+    /// Read off the first leaf still held, so `None` only if there is none.
+    fn leaf_hash_len(&self) -> Option<usize> {
+        let first = self.pruned_to.as_ref().map_or(Some(0), |kept| kept.first().copied())?;
+        self.node_at(self.leaf_count, first).map(|leaf| leaf.hash.len())
+    }
+
+    /// Hash up from a leaf along the proof and compare with the root
+    fn reconstructs_root(
+        &self,
+        leaf_hash: Vec<u8>,
+        proof: &[Vec<u8>],
+        proof_path: &[bool],
+    ) -> bool {
+        let current_hash = self.hasher.root_from_proof(leaf_hash, proof, proof_path);
+
+        // Compare the computed root hash with the tree's root hash
+        self.root.as_ref().is_some_and(|root| current_hash == root.hash)
+    }
+}
+
+impl Clone for Node {
+    fn clone(&self) -> Self {
+        Node {
+            hash: self.hash.clone(),
+            left: self.left.clone(),
+            right: self.right.clone(),
+        }
+    }
+}
+
+/// The result of `MerkleTree::verify_detailed`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyOutcome {
+    /// The proof reconstructs the tree's root
+    Valid,
+    /// The tree has no leaves, so there is no root to verify against
+    EmptyTree,
+    /// The proof and its path have different lengths
+    LengthMismatch { proof_len: usize, path_len: usize },
+    /// The proof is longer than the tree's maximum proof length
+    ProofTooLong { len: usize, max: usize },
+    /// No leaf of a tree this size has the proof's path
+    ImpossiblePath,
+    /// A sibling hash in the proof has the wrong number of bytes
+    InvalidSiblingLength { index: usize, len: usize, expected: usize },
+    /// The proof is well-formed but leads to a different root
+    RootMismatch { computed: Hash, expected: Hash },
+}
+
+/// Structural metrics returned by `MerkleTree::stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeStats {
+    /// Number of leaves
+    pub leaf_count: usize,
+    /// Number of levels above the leaves; 0 for an empty or single-leaf tree
+    pub height: usize,
+    /// Number of distinct nodes, leaves included
+    pub node_count: usize,
+    /// Number of times an odd node was promoted to the next level unchanged
+    pub promoted_nodes: usize,
+    /// Whether the leaf count is a power of two, so no node is ever promoted
+    pub is_perfect: bool,
+}
+
+/// Parse a whitespace-separated list of leaves, e.g. `"a b c d"`
+///
+/// The input is split on any run of whitespace, so leading and trailing
+/// whitespace is ignored and repeated spaces, tabs or newlines never produce
+/// empty leaves. Each word is hashed as its UTF-8 bytes, as with `from_strs`.
+/// Fails with `MerkleError::EmptyTree` if there are no words.
+impl TryFrom<&str> for MerkleTree {
+    type Error = MerkleError;
+
+    fn try_from(leaves: &str) -> Result<Self, Self::Error> {
+        let words: Vec<&str> = leaves.split_whitespace().collect();
+        if words.is_empty() {
+            return Err(MerkleError::EmptyTree);
+        }
+        Ok(MerkleTree::from_strs(&words))
+    }
+}
+
+impl From<Vec<Vec<u8>>> for MerkleTree {
+    /// Build a tree with `MerkleTree::new`; empty input gives an empty tree
+    fn from(data_blocks: Vec<Vec<u8>>) -> Self {
+        MerkleTree::new(data_blocks)
+    }
+}
+
+/// Configures and builds a `MerkleTree`
+#[derive(Debug, Clone, Default)]
+pub struct MerkleTreeBuilder {
+    hasher: TreeHasher,
+    max_proof_len: Option<usize>,
+    retain_leaf_data: bool,
+    max_leaf_size: Option<usize>,
+}
+
+impl MerkleTreeBuilder {
+    /// Create a builder with the default configuration (SHA-256 throughout)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the hash function applied once to each data block
+    pub fn leaf_hasher(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hasher.leaf = algorithm;
+        self
+    }
+
+    /// Set the hash function applied to each pair of child hashes
+    pub fn node_hasher(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hasher.node = algorithm;
+        self
+    }
+
+    /// Hash both leaves and internal nodes with HMAC-SHA256 under `key`
+    ///
+    /// Verifying proofs against the resulting tree's root requires a tree
+    /// configured with the same key.
+    pub fn keyed(self, key: impl Into<Vec<u8>>) -> Self {
+        let algorithm = HashAlgorithm::HmacSha256(key.into());
+        self.leaf_hasher(algorithm.clone()).node_hasher(algorithm)
+    }
+
+    /// Insert `separator` between the two child hashes when hashing internal
+    /// nodes, as some legacy systems do (no separator by default)
+    ///
+    /// Trees with a separator longer than 255 bytes cannot be serialized.
+    pub fn node_separator(mut self, separator: Vec<u8>) -> Self {
+        self.hasher.node_separator = Some(separator);
+        self
+    }
+
+    /// Hash each data block prefixed with its length, 8 bytes little-endian,
+    /// so that blocks cannot be re-split into different leaves with the same
+    /// hashes (not prefixed by default)
+    ///
+    /// Proofs against the resulting tree verify with its own `verify`.
+    pub fn length_prefixed_leaves(mut self) -> Self {
+        self.hasher.length_prefixed_leaves = true;
+        self
+    }
+
+    /// Set the longest proof the tree will attempt to verify
+    /// (defaults to `DEFAULT_MAX_PROOF_LEN`)
+    pub fn max_proof_len(mut self, max_proof_len: usize) -> Self {
+        self.max_proof_len = Some(max_proof_len);
+        self
+    }
+
+    /// Keep the data blocks in the tree alongside their hashes, so they can
+    /// be read back with `leaf_data` (not retained by default)
+    pub fn retain_leaf_data(mut self) -> Self {
+        self.retain_leaf_data = true;
+        self
+    }
+
+    /// Reject data blocks longer than `max_leaf_size` bytes, e.g. when
+    /// building from untrusted input (unlimited by default)
+    ///
+    /// The limit is checked by `try_build`; `build` panics on an oversized
+    /// block.
+    pub fn max_leaf_size(mut self, max_leaf_size: usize) -> Self {
+        self.max_leaf_size = Some(max_leaf_size);
+        self
+    }
+
+    /// Build a tree over the given data blocks with this configuration,
+    /// failing with `MerkleError::LeafTooLarge` for the first block over
+    /// `max_leaf_size`
+    pub fn try_build(self, data_blocks: Vec<Vec<u8>>) -> Result<MerkleTree, MerkleError> {
+        if let Some(max_leaf_size) = self.max_leaf_size
+            && let Some((index, data)) = data_blocks.iter()
+                .enumerate()
+                .find(|(_, data)| data.len() > max_leaf_size)
+        {
+            return Err(MerkleError::LeafTooLarge { index, size: data.len() });
+        }
+        Ok(self.build_unchecked(data_blocks))
+    }
+
+    /// Build a tree over the given data blocks with this configuration
+    ///
+    /// Panics if a block is over `max_leaf_size`; use `try_build` for input
+    /// that may be.
+    pub fn build(self, data_blocks: Vec<Vec<u8>>) -> MerkleTree {
+        match self.try_build(data_blocks) {
+            Ok(tree) => tree,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Build a tree whose leaves are the given hashes, used as-is, with this
+    /// configuration's node hashing, e.g. over a Bitcoin block's txids
+    ///
+    /// As with `MerkleTree::from_hashes` the hashes are not checked, and
+    /// there is no leaf data to retain.
+    pub fn build_from_hashes(self, leaf_hashes: Vec<Hash>) -> MerkleTree {
+        let nodes = leaf_hashes.into_iter().map(Node::from_hash).collect();
+        let mut tree = MerkleTree::build(nodes, self.hasher);
+        if let Some(max_proof_len) = self.max_proof_len {
+            tree.max_proof_len = max_proof_len;
+        }
+        tree
+    }
+
+    fn build_unchecked(self, data_blocks: Vec<Vec<u8>>) -> MerkleTree {
+        let mut tree = MerkleTree::with_hasher(&data_blocks, self.hasher);
+        if let Some(max_proof_len) = self.max_proof_len {
+            tree.max_proof_len = max_proof_len;
+        }
+        if self.retain_leaf_data {
+            tree.leaf_data = Some(data_blocks);
+        }
+        tree
+    }
+}
+
+/// Iterator over the leaf hashes of a `MerkleTree`, left to right
+#[derive(Debug)]
+pub struct Leaves<'a> {
+    stack: Vec<&'a Node>,
+}
+
+impl<'a> Iterator for Leaves<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Depth-first, visiting left children before right ones
+        while let Some(node) = self.stack.pop() {
+            match (&node.left, &node.right) {
+                (Some(left), Some(right)) => {
+                    self.stack.push(right);
+                    self.stack.push(left);
+                }
+                _ => return Some(&node.hash),
+            }
+        }
+        None
+    }
+}
+
+impl<'a, M> IntoIterator for &'a MerkleTree<M> {
+    type Item = &'a [u8];
+    type IntoIter = Leaves<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.leaves()
+    }
+}
+
+/// Build the tree bottom-up from a list of leaf nodes, returning its root
+///
+/// Each level is drained and its nodes moved into their parents. Pairing
+/// with `chunks(2)` instead needs a clone of both subtrees per parent, which
+/// made building 100K leaves over 10x slower (`cargo bench --bench pairing`).
+///
+/// With the `tracing` feature each level runs in a `build_level` span
+/// recording the number of nodes it pairs up, so a subscriber can time them.
+fn build_root(mut nodes: Vec<Node>, hasher: &TreeHasher) -> Option<Node> {
+    if nodes.is_empty() {
+        return None;
+    }
+
+    // Build the tree bottom-up
+    while nodes.len() > 1 {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("build_level", nodes = nodes.len()).entered();
+
+        // Each level holds half of the previous one, rounded up
+        let mut next_level = Vec::with_capacity(nodes.len().div_ceil(2));
+
+        // Process pairs of nodes
+        let mut level = nodes.into_iter();
+        while let Some(left) = level.next() {
+            match level.next() {
+                // Create a parent node with two children
+                Some(right) => next_level.push(Node::new_parent(left, right, hasher)),
+                // If there's an odd node left, promote it to the next level
+                None => next_level.push(left),
+            }
+        }
+
+        nodes = next_level;
+    }
+
+    Some(nodes.remove(0))
+}
+
+/// The number of leaves under the left child of a node over `width` leaves,
+/// the largest power of two below `width`
+///
+/// Returns `None` if `width` is over `MAX_LEAVES`, where the power of two
+/// that halves to it overflows.
+pub(crate) fn checked_split(width: usize) -> Option<usize> {
+    width.checked_next_power_of_two().map(|power| power / 2)
+}
+
+/// Drop the children of every node under `node`, which covers the `width`
+/// leaves from `first`, whose subtree holds none of the sorted `kept` leaves
+fn prune_node(node: &mut Node, first: usize, width: usize, kept: &[usize]) {
+    let start = kept.partition_point(|&index| index < first);
+    if kept.get(start).is_none_or(|&index| index >= first + width) {
+        node.left = None;
+        node.right = None;
+        return;
+    }
+
+    let left_width = checked_split(width).expect("tree is within MAX_LEAVES");
+    if let (Some(left), Some(right)) = (node.left.as_deref_mut(), node.right.as_deref_mut()) {
+        prune_node(left, first, left_width, kept);
+        prune_node(right, first + left_width, width - left_width, kept);
+    }
+}
+
+/// The index of the leaf whose proof path is `proof_path` in a tree of
+/// `leaf_count` leaves, the inverse of `proof_path_for`
+///
+/// Reads the path from the root down. Promoted nodes are never split, so the
+/// left child of a node over `width` leaves always covers the largest power of
+/// two below `width`, and every split consumes exactly one path entry.
+fn leaf_index_for_path(proof_path: &[bool], leaf_count: usize) -> Option<usize> {
+    let mut directions = proof_path.iter().rev();
+    let mut index: usize = 0;
+    let mut width = leaf_count;
+    while width > 1 {
+        let left_width = checked_split(width)?;
+        if *directions.next()? {
+            index = index.checked_add(left_width)?;
+            width -= left_width;
+        } else {
+            width = left_width;
+        }
+    }
+
+    (width == 1 && directions.next().is_none()).then_some(index)
+}
+
+/// Reject proofs that no tree of `leaf_count` leaves can verify, before any
+/// hashing is done
+///
+/// Every tree type checks its proofs here, so they agree on what is
+/// malformed. `hash_lens` are the lengths of the tree's leaf and internal
+/// node hashes, either of which a sibling may be.
+pub(crate) fn check_proof_shape(
+    proof: &[Vec<u8>],
+    proof_path: &[bool],
+    leaf_count: usize,
+    max_proof_len: usize,
+    hash_lens: (usize, usize),
+) -> Result<(), MerkleError> {
+    if proof.len() > max_proof_len {
+        return Err(MerkleError::ProofTooLong { len: proof.len(), max: max_proof_len });
+    }
+    if proof.len() != proof_path.len() {
+        return Err(MerkleError::PathLengthMismatch {
+            proof_len: proof.len(),
+            path_len: proof_path.len(),
+        });
+    }
+    if !is_possible_path(proof_path, leaf_count) {
+        return Err(MerkleError::ImpossiblePath { leaf_count });
+    }
+
+    let (leaf_len, node_len) = hash_lens;
+    if let Some((index, sibling)) = proof.iter()
+        .enumerate()
+        .find(|(_, sibling)| sibling.len() != leaf_len && sibling.len() != node_len)
+    {
+        return Err(MerkleError::InvalidSiblingLength {
+            index,
+            len: sibling.len(),
+            expected: node_len,
+        });
+    }
+    Ok(())
+}
+
+/// Whether `proof_path` could be a path of a tree of `leaf_count` leaves
+///
+/// Read from the root down, the path has to reach a leaf before it runs out.
+/// Any entries left over below are let through, as the proof may be a
+/// composed proof (see `MerkleTree::compose_proofs`) that climbs through a
+/// sub-tree first and only then through this tree, and a sub-tree of any
+/// shape may hang from a promoted leaf.
+fn is_possible_path(proof_path: &[bool], leaf_count: usize) -> bool {
+    let mut directions = proof_path.iter().rev();
+    let mut width = leaf_count;
+    while width > 1 {
+        let Some(left_width) = checked_split(width) else {
+            return false;
+        };
+        match directions.next() {
+            Some(true) => width -= left_width,
+            Some(false) => width = left_width,
+            None => return false,
+        }
+    }
+    width == 1
+}
+
+/// The proof path for the leaf at `index` in a tree of `leaf_count` leaves
+///
+/// Records the direction taken at each level, bottom-up, skipping levels
+/// where the leaf's ancestor is promoted without a sibling.
+fn proof_path_for(index: usize, leaf_count: usize) -> Vec<bool> {
+    let mut proof_path = Vec::new();
+    let mut position = index;
+    let mut width = leaf_count;
+    while width > 1 {
+        if position % 2 == 1 {
+            proof_path.push(true);
+        } else if position + 1 < width {
+            proof_path.push(false);
+        }
+        position /= 2;
+        width = width.div_ceil(2);
+    }
+    proof_path
+}
+
+/// Verify a proof for a data block against a known SHA-256 root hash,
+/// without needing the tree itself
+///
+/// This is fully stateless: it succeeds exactly when hashing up from the
+/// data along the siblings reproduces `root`, and assumes nothing about the
+/// tree's size. The only checks are that there is one path entry per
+/// sibling and no more than `DEFAULT_MAX_PROOF_LEN` of them, the depth of a
+/// tree with the most leaves a `usize` can count. The proof is therefore
+/// not tied to a leaf position; size-aware callers wanting that opt in with
+/// `MerkleTree::verify_with_index`.
+pub fn verify_proof(root: &[u8], data: &[u8], proof: &[Vec<u8>], proof_path: &[bool]) -> bool {
+    verify_proof_with::<Sha256>(root, data, proof, proof_path)
+}
+
+/// Verify a proof from `generate_padded_proof` against a known SHA-256 root
+/// hash, requiring it to be exactly `depth` siblings long
+///
+/// Trailing padding entries, a `PADDING_SIBLING` with path `Side::Left`, so
+/// the running hash on the left and the padding on the right, are skipped
+/// and everything below them is checked as with `verify_proof`.
+pub fn verify_padded_proof(root: &[u8], data: &[u8], proof: &Proof, depth: usize) -> bool {
+    if proof.siblings.len() != depth || proof.path.len() != depth {
+        return false;
+    }
+    let padding = proof.siblings.iter()
+        .zip(&proof.path)
+        .rev()
+        .take_while(|(sibling, side)| sibling[..] == PADDING_SIBLING && **side == Side::Left)
+        .count();
+    let proof_path = proof.path_bits();
+    verify_proof(root, data, &proof.siblings[..depth - padding], &proof_path[..depth - padding])
+}
+
+/// Verify a proof for a data block against several candidate SHA-256 roots,
+/// returning the index of the first root it matches
+///
+/// The implied root is computed once, so this is cheaper than calling
+/// `verify_proof` for each candidate.
+pub fn verify_against_any(
+    roots: &[&[u8]],
+    data: &[u8],
+    proof: &[Vec<u8>],
+    proof_path: &[bool],
+) -> Option<usize> {
+    if proof.len() != proof_path.len() || proof.len() > DEFAULT_MAX_PROOF_LEN {
+        return None;
+    }
+
+    let hasher = TreeHasher::default();
+    let computed = hasher.root_from_proof(hasher.hash_leaf(data), proof, proof_path);
+    roots.iter().position(|root| *root == computed)
+}
+
+/// Verify a proof against a known root hash for a tree that hashes both its
+/// leaves and internal nodes with the digest `D`
+pub fn verify_proof_with<D: Digest + FixedOutputReset>(
+    root: &[u8],
+    data: &[u8],
+    proof: &[Vec<u8>],
+    proof_path: &[bool],
+) -> bool {
+    if proof.len() != proof_path.len() || proof.len() > DEFAULT_MAX_PROOF_LEN {
+        return false;
+    }
+
+    fold_proof::<D>(D::digest(data).to_vec(), proof, proof_path) == root
+}
+
+/// Verify a sorted-pair proof, as used by OpenZeppelin's `MerkleProof`,
+/// against a known SHA-256 root hash
+///
+/// Sorted-pair trees order each pair of hashes lexicographically before
+/// hashing them, so a proof is just the sibling hashes and carries no path.
+/// These proofs cannot be checked with `verify_proof`, and proofs from this
+/// crate's trees cannot be checked here.
+pub fn verify_unordered(root: &[u8], leaf_hash: &[u8], proof: &[Hash]) -> bool {
+    verify_unordered_with::<Sha256>(root, leaf_hash, proof)
+}
+
+/// Verify a sorted-pair proof against a known root hash for a tree that
+/// hashes its internal nodes with the digest `D`, e.g. Keccak-256 for
+/// OpenZeppelin trees
+pub fn verify_unordered_with<D: Digest>(root: &[u8], leaf_hash: &[u8], proof: &[Hash]) -> bool {
+    if proof.len() > DEFAULT_MAX_PROOF_LEN {
+        return false;
+    }
+
+    let mut current_hash = leaf_hash.to_vec();
+    for sibling_hash in proof {
+        current_hash = if current_hash <= *sibling_hash {
+            digest_parts::<D>(&[&current_hash, sibling_hash])
+        } else {
+            digest_parts::<D>(&[sibling_hash, &current_hash])
+        };
+    }
+
+    current_hash == root
+}
+
+/// Compute the Merkle root of a list of data blocks
+pub fn build_merkle_root(data_blocks: Vec<Vec<u8>>) -> Option<Vec<u8>> {
+    MerkleTree::new(data_blocks).root_hash()
+}
+
+/// Get the smallest and largest leaf counts consistent with a proof path
+///
+/// A tree of height `h` holds between `2^(h-1) + 1` and `2^h` leaves, and no
+/// path is longer than the height. A path whose last, topmost entry is
+/// `false` climbs out of the root's left child, which is always a perfect
+/// subtree, so its length is exactly the height. A path ending in `true` may
+/// come from a promoted leaf with a shorter path in an arbitrarily large
+/// tree, so for it the largest count is `usize::MAX`.
+pub fn proof_implied_size_range(proof_path: &[bool]) -> (usize, usize) {
+    if proof_path.is_empty() {
+        return (1, 1);
+    }
+
+    let height = u32::try_from(proof_path.len()).unwrap_or(u32::MAX);
+    let min = 1usize.checked_shl(height - 1).map_or(usize::MAX, |half| half + 1);
+    let max = match proof_path.last() {
+        Some(false) => 1usize.checked_shl(height).unwrap_or(usize::MAX),
+        _ => usize::MAX,
+    };
+
+    (min, max)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,133 @@
+//
+// Merkle Mountain Ranges: append-only trees with stable proofs
+//
+// `MerkleTree::push` rebuilds the balanced tree, which can change the shape
+// above existing leaves and so the siblings in their proofs. A mountain range
+// never changes a node once it exists. Leaves are paired into perfect
+// subtrees like the carries of a binary counter: after each append, two
+// subtrees of equal height merge under a new parent, leaving one "peak" per
+// set bit of the leaf count.
+//
+// Level `h` holds the nodes of height `h` from left to right, so the ancestor
+// of leaf `i` at height `h` is `levels[h][i >> h]`. A proof leads from a leaf
+// to the peak above it at the time the proof was made. That node stays in
+// place after later appends, even when it stops being a peak, so the proof
+// keeps verifying; a newer proof for the same leaf is just longer.
+//
+
+use crate::{Hash, TreeHasher};
+
+/// An append-only Merkle structure whose proofs stay valid as it grows
+///
+/// Compared to the balanced `MerkleTree`, proofs verify against the node they
+/// lead to rather than against a single root hash, so the verifier needs this
+/// structure (or the hashes its proofs end at) instead of one 32-byte root.
+/// The commitment to all leaves, `root`, bags up to one peak per level and
+/// matches the root `MerkleTree` computes over the same leaves.
+#[derive(Debug, Clone, Default)]
+pub struct MerkleMountainRange {
+    levels: Vec<Vec<Hash>>,
+    hasher: TreeHasher,
+}
+
+impl MerkleMountainRange {
+    /// Create an empty mountain range hashing with SHA-256
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a data block as a new leaf, returning its index
+    ///
+    /// Existing nodes are never changed, only new parents added.
+    pub fn push(&mut self, data: &[u8]) -> usize {
+        let index = self.leaf_count();
+        let mut hash = self.hasher.hash_leaf(data);
+        let mut height = 0;
+        loop {
+            if self.levels.len() == height {
+                self.levels.push(Vec::new());
+            }
+            let level = &mut self.levels[height];
+            level.push(hash);
+            if level.len() % 2 == 1 {
+                break;
+            }
+            // The new node completes a pair, whose parent goes a level up
+            hash = self.hasher.hash_children(&level[level.len() - 2], &level[level.len() - 1]);
+            height += 1;
+        }
+        index
+    }
+
+    /// Get the number of leaves
+    pub fn leaf_count(&self) -> usize {
+        self.levels.first().map_or(0, |leaves| leaves.len())
+    }
+
+    /// Get the peaks, the roots of the perfect subtrees, from left to right
+    pub fn peaks(&self) -> Vec<&[u8]> {
+        self.levels.iter()
+            .rev()
+            .filter(|level| level.len() % 2 == 1)
+            .filter_map(|level| level.last().map(|hash| hash.as_slice()))
+            .collect()
+    }
+
+    /// Get a single hash committing to every leaf, made by hashing the peaks
+    /// together from right to left
+    pub fn root(&self) -> Option<Hash> {
+        let mut peaks = self.peaks().into_iter().rev();
+        let first = peaks.next()?.to_vec();
+        Some(peaks.fold(first, |bagged, peak| self.hasher.hash_children(peak, &bagged)))
+    }
+
+    /// Generate a proof from the leaf at `index` up to the peak above it
+    ///
+    /// The proof keeps verifying after any number of later appends.
+    pub fn generate_proof(&self, index: usize) -> Option<(Vec<Vec<u8>>, Vec<bool>)> {
+        if index >= self.leaf_count() {
+            return None;
+        }
+
+        let mut proof = Vec::new();
+        let mut proof_path = Vec::new();
+        for (height, level) in self.levels.iter().enumerate() {
+            let position = index >> height;
+            let has_parent = self.levels.get(height + 1)
+                .is_some_and(|parents| position / 2 < parents.len());
+            if !has_parent {
+                break;
+            }
+            proof.push(level[position ^ 1].clone());
+            proof_path.push(position % 2 == 1);
+        }
+        Some((proof, proof_path))
+    }
+
+    /// Verify that a data block is the leaf at `index` with a proof from
+    /// `generate_proof`, made before or after any later appends
+    pub fn verify(
+        &self,
+        index: usize,
+        data: &[u8],
+        proof: &[Vec<u8>],
+        proof_path: &[bool],
+    ) -> bool {
+        if proof.len() != proof_path.len() {
+            return false;
+        }
+        // The path must be the one the leaf's position dictates
+        let position_matches = proof_path.iter()
+            .enumerate()
+            .all(|(height, &is_right)| is_right == ((index >> height) % 2 == 1));
+        let Some(expected) = self.levels.get(proof.len())
+            .and_then(|level| level.get(index >> proof.len()))
+        else {
+            return false;
+        };
+
+        position_matches
+            && self.hasher.root_from_proof(self.hasher.hash_leaf(data), proof, proof_path)
+                == *expected
+    }
+}
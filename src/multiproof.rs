@@ -0,0 +1,135 @@
+//
+// Multiproofs: a single proof covering several leaves at once
+//
+// Proving several leaves separately repeats every sibling hash their paths
+// share. A multiproof walks up from all of the proven leaves together and
+// only includes the hashes that cannot be computed from the leaves
+// themselves or from nodes already derived from them.
+//
+
+use crate::{MerkleTree, TreeHasher};
+
+/// An inclusion proof for several leaves of a tree at once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiProof {
+    leaf_count: usize,
+    indices: Vec<usize>,
+    hashes: Vec<Vec<u8>>,
+}
+
+impl MultiProof {
+    /// Get the leaf indices this proof claims to cover, in ascending order
+    ///
+    /// Data passed to `MerkleTree::verify_multiproof` must follow this order.
+    pub fn covered_indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+    /// Get the number of leaves in the tree the proof was generated for
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Get the sibling hashes the verifier cannot compute itself, in the
+    /// order verification consumes them: bottom-up, left to right
+    pub fn hashes(&self) -> &[Vec<u8>] {
+        &self.hashes
+    }
+}
+
+impl<M> MerkleTree<M> {
+    /// Generate one proof covering all of the leaves at `indices`
+    ///
+    /// Duplicate indices are ignored. Returns `None` if no indices are given
+    /// or any of them is outside the tree.
+    pub fn multiproof(&self, indices: &[usize]) -> Option<MultiProof> {
+        let mut indices = indices.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+        if indices.is_empty() || indices.iter().any(|&index| index >= self.leaf_count) {
+            return None;
+        }
+
+        let leaves: Vec<&[u8]> = self.leaves().collect();
+        let known = indices.iter().map(|&index| (index, leaves[index].to_vec())).collect();
+
+        let mut hashes = Vec::new();
+        fold_known(&self.hasher, self.leaf_count, known, |level_width, position| {
+            let hash = self.node_at(level_width, position)?.hash.clone();
+            hashes.push(hash.clone());
+            Some(hash)
+        })?;
+
+        Some(MultiProof { leaf_count: self.leaf_count, indices, hashes })
+    }
+
+    /// Verify a multiproof, given the data blocks of the covered leaves in
+    /// the order of `MultiProof::covered_indices`
+    pub fn verify_multiproof(&self, data: &[&[u8]], proof: &MultiProof) -> bool {
+        let Some(root) = &self.root else {
+            return false;
+        };
+        let sorted = proof.indices.windows(2).all(|pair| pair[0] < pair[1]);
+        if proof.leaf_count != self.leaf_count
+            || proof.indices.is_empty()
+            || data.len() != proof.indices.len()
+            || !sorted
+            || proof.indices.last().is_some_and(|&index| index >= self.leaf_count)
+        {
+            return false;
+        }
+
+        let known = proof.indices.iter()
+            .zip(data)
+            .map(|(&index, block)| (index, self.hasher.hash_leaf(block)))
+            .collect();
+
+        let mut hashes = proof.hashes.iter();
+        let computed = fold_known(&self.hasher, self.leaf_count, known, |_, _| {
+            hashes.next().cloned()
+        });
+
+        // Every supplied hash must have been needed
+        computed.is_some_and(|computed| computed == root.hash) && hashes.next().is_none()
+    }
+}
+
+/// Hash up from known nodes of the bottom level to the root
+///
+/// `known` holds `(position, hash)` pairs sorted by position. Whenever a
+/// node's sibling is not known, `sibling` is asked for the hash at that
+/// position on the level of the given width.
+fn fold_known(
+    hasher: &TreeHasher,
+    leaf_count: usize,
+    mut known: Vec<(usize, Vec<u8>)>,
+    mut sibling: impl FnMut(usize, usize) -> Option<Vec<u8>>,
+) -> Option<Vec<u8>> {
+    let mut width = leaf_count;
+    while width > 1 {
+        let mut next_level = Vec::with_capacity(known.len());
+        let mut i = 0;
+        while i < known.len() {
+            let (position, hash) = &known[i];
+            let position = *position;
+            if position % 2 == 1 {
+                let left = sibling(width, position - 1)?;
+                next_level.push((position / 2, hasher.hash_children(&left, hash)));
+            } else if position + 1 == width {
+                // The odd node at the end is promoted unchanged
+                next_level.push((position / 2, hash.clone()));
+            } else if known.get(i + 1).is_some_and(|(next, _)| *next == position + 1) {
+                next_level.push((position / 2, hasher.hash_children(hash, &known[i + 1].1)));
+                i += 1;
+            } else {
+                let right = sibling(width, position + 1)?;
+                next_level.push((position / 2, hasher.hash_children(hash, &right)));
+            }
+            i += 1;
+        }
+        known = next_level;
+        width = width.div_ceil(2);
+    }
+
+    known.pop().map(|(_, hash)| hash)
+}
@@ -0,0 +1,200 @@
+//
+// Partial trees: which leaves a set of subtree hashes can verify
+//
+// While a tree is downloaded piecemeal, only some hashes are known. A
+// subtree hash is verified once it is tied to the trusted root, that is when
+// the hashes held for the rest of the tree are enough to rebuild the root
+// from it, and then any data for its leaves can be checked against it alone.
+// Larger subtree hashes stand in for the parts of the tree not fetched yet,
+// like the pruned branches of Bitcoin's partial merkle trees in a filtered
+// block, except that the hashes may arrive in any order.
+//
+// Hashes are not trusted as they arrive, so a node may end up with several
+// candidate hashes: the one recorded for it and those rebuilt from below.
+// Which one is real is only known once one of them leads to the root. Every
+// conflicting hash multiplies the candidates above it, so at most
+// `MAX_CANDIDATES` are kept per node, and a range buried under more
+// conflicting hashes than that may go unreported.
+//
+// Subtrees are identified by the range of leaves below them and must be
+// nodes of the canonical shape, where the left child of a node over `width`
+// leaves covers `width.next_power_of_two() / 2` of them.
+//
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use crate::{checked_split, BitcoinPartialMerkleTree, Hash, MerkleError, TreeHasher, MAX_LEAVES};
+
+/// The most candidate hashes kept for one node
+const MAX_CANDIDATES: usize = 4;
+
+/// Candidate hashes for the nodes looked at, by leaf range
+type Candidates = BTreeMap<(usize, usize), Vec<Hash>>;
+
+/// A tree known only by its root and some of its subtree hashes
+#[derive(Debug, Clone)]
+pub struct PartialMerkleTree {
+    root: Hash,
+    leaf_count: usize,
+    subtrees: BTreeMap<(usize, usize), Hash>,
+    hasher: TreeHasher,
+}
+
+impl PartialMerkleTree {
+    /// Start from the trusted SHA-256 root of a tree with `leaf_count` leaves
+    pub fn new(root: Hash, leaf_count: usize) -> Self {
+        PartialMerkleTree {
+            root,
+            leaf_count,
+            subtrees: BTreeMap::new(),
+            hasher: TreeHasher::default(),
+        }
+    }
+
+    /// Record the hash of the subtree over `leaves`
+    ///
+    /// For a single leaf this is its leaf hash. Fails if the range is not
+    /// covered by one node of the tree, or with `MerkleError::TooManyLeaves`
+    /// if the tree has more than `MAX_LEAVES` leaves. A hash that turns out not to match
+    /// is passed over in `verified_ranges`, which still rebuilds the node
+    /// from the hashes below it.
+    pub fn add_subtree(&mut self, leaves: Range<usize>, hash: Hash) -> Result<(), MerkleError> {
+        if self.leaf_count > MAX_LEAVES {
+            return Err(MerkleError::TooManyLeaves { max: MAX_LEAVES });
+        }
+        if !self.is_node(leaves.start, leaves.end) {
+            return Err(MerkleError::NotASubtree { start: leaves.start, end: leaves.end });
+        }
+        self.subtrees.insert((leaves.start, leaves.end), hash);
+        Ok(())
+    }
+
+    /// Get the ranges of leaves under a recorded subtree hash that is tied
+    /// to the root, merging adjacent ranges
+    ///
+    /// Two sibling subtree hashes that rebuild their parent both count, in
+    /// full: with the hashes of `0..2` and `2..4` of a four-leaf tree, all of
+    /// `0..4` is verified.
+    pub fn verified_ranges(&self) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        // Nothing can be recorded in a tree over more than `MAX_LEAVES`
+        if self.leaf_count == 0 || self.leaf_count > MAX_LEAVES {
+            return ranges;
+        }
+        let mut candidates = Candidates::new();
+        self.collect_candidates(0, self.leaf_count, &mut candidates);
+        self.collect_verified(0, self.leaf_count, &self.root, &candidates, &mut ranges);
+        ranges
+    }
+
+    /// Get the number of leaves in the full tree
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Parse a Bitcoin `CPartialMerkleTree` from a `merkleblock` message
+    ///
+    /// Bitcoin's trees duplicate odd nodes rather than promote them, so the
+    /// result is a `BitcoinPartialMerkleTree` rather than a tree of this
+    /// shape; see `BitcoinPartialMerkleTree::from_bitcoin_bytes`.
+    pub fn from_bitcoin_bytes(
+        flags: &[u8],
+        hashes: &[u8],
+        tx_count: u32,
+    ) -> Result<BitcoinPartialMerkleTree, MerkleError> {
+        BitcoinPartialMerkleTree::from_bitcoin_bytes(flags, hashes, tx_count)
+    }
+
+    fn is_node(&self, start: usize, end: usize) -> bool {
+        let (mut node_start, mut node_end) = (0, self.leaf_count);
+        while node_end - node_start > 1 && (node_start, node_end) != (start, end) {
+            let mid = node_start + split(node_end - node_start);
+            if end <= mid {
+                node_end = mid;
+            } else if start >= mid {
+                node_start = mid;
+            } else {
+                return false;
+            }
+        }
+        start < end && (node_start, node_end) == (start, end)
+    }
+
+    /// Find the candidate hashes of the node over `start..end` and the nodes
+    /// below it, rebuilt ones first, returning those of the node itself
+    fn collect_candidates(
+        &self,
+        start: usize,
+        end: usize,
+        candidates: &mut Candidates,
+    ) -> Vec<Hash> {
+        let mut found = Vec::new();
+        // A single leaf is only known directly
+        if end - start > 1 {
+            let mid = start + split(end - start);
+            let left = self.collect_candidates(start, mid, candidates);
+            // Without the left child the right one cannot help rebuild this node
+            let right = if left.is_empty() {
+                Vec::new()
+            } else {
+                self.collect_candidates(mid, end, candidates)
+            };
+            for left in &left {
+                for right in &right {
+                    found.push(self.hasher.hash_children(left, right));
+                }
+            }
+        }
+        found.extend(self.subtrees.get(&(start, end)).cloned());
+        let mut distinct: Vec<Hash> = Vec::new();
+        for hash in found {
+            if distinct.len() < MAX_CANDIDATES && !distinct.contains(&hash) {
+                distinct.push(hash);
+            }
+        }
+        candidates.insert((start, end), distinct.clone());
+        distinct
+    }
+
+    /// Walk down from a node whose hash is verified, adding the ranges below
+    /// it whose recorded hashes are tied to it
+    fn collect_verified(
+        &self,
+        start: usize,
+        end: usize,
+        hash: &[u8],
+        candidates: &Candidates,
+        ranges: &mut Vec<Range<usize>>,
+    ) {
+        if self.subtrees.get(&(start, end)).is_some_and(|known| known == hash) {
+            match ranges.last_mut() {
+                Some(last) if last.end == start => last.end = end,
+                _ => ranges.push(start..end),
+            }
+            return;
+        }
+        if end - start == 1 {
+            return;
+        }
+        let mid = start + split(end - start);
+        let none = Vec::new();
+        let left = candidates.get(&(start, mid)).unwrap_or(&none);
+        let right = candidates.get(&(mid, end)).unwrap_or(&none);
+        for left in left {
+            for right in right {
+                if self.hasher.hash_children(left, right) == hash {
+                    self.collect_verified(start, mid, left, candidates, ranges);
+                    self.collect_verified(mid, end, right, candidates, ranges);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// The number of leaves under the left child of a node over `width` leaves,
+/// which no larger than the tree is within `MAX_LEAVES`
+fn split(width: usize) -> usize {
+    checked_split(width).expect("partial tree is within MAX_LEAVES")
+}
@@ -0,0 +1,48 @@
+//
+// Watching the root evolve as leaves arrive
+//
+// `RootProgression` appends each leaf of an iterator to a mountain range and
+// yields the root after it. Bagging the peaks from right to left gives the
+// same root as the canonical shape, so each yielded root is the one
+// `MerkleTree` would compute over the leaves so far, at a cost of
+// `O(log n)` hashes per leaf instead of a rebuild.
+//
+
+use crate::{Hash, MerkleMountainRange};
+
+/// An iterator yielding the root hash after each leaf of another iterator
+#[derive(Debug, Clone)]
+pub struct RootProgression<I> {
+    leaves: I,
+    range: MerkleMountainRange,
+}
+
+impl<I: Iterator> RootProgression<I>
+where
+    I::Item: AsRef<[u8]>,
+{
+    /// Wrap an iterator of data blocks, hashing with SHA-256
+    pub fn new(leaves: impl IntoIterator<IntoIter = I>) -> Self {
+        RootProgression {
+            leaves: leaves.into_iter(),
+            range: MerkleMountainRange::new(),
+        }
+    }
+}
+
+impl<I: Iterator> Iterator for RootProgression<I>
+where
+    I::Item: AsRef<[u8]>,
+{
+    type Item = Hash;
+
+    fn next(&mut self) -> Option<Hash> {
+        let data = self.leaves.next()?;
+        self.range.push(data.as_ref());
+        self.range.root()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.leaves.size_hint()
+    }
+}
@@ -0,0 +1,189 @@
+//
+// Standalone inclusion proofs
+//
+// A `Proof` holds only hashes, directions and the leaf index, never tree
+// nodes, so it can be stored, sent and verified without the tree that made
+// it, and consumers are unaffected by how a tree lays out its nodes.
+//
+// With the `serde` feature, proofs convert to and from JSON of exactly this
+// form, for exchange with other implementations:
+//
+//   {
+//     "leaf_index": 2,
+//     "siblings": ["<64 lowercase hex digits>", ...],
+//     "path": ["left", "right", ...]
+//   }
+//
+// `leaf_index` is a non-negative integer, every sibling a 32-byte SHA-256
+// hash, and `path` holds one entry per sibling from the leaf upwards, naming
+// the side the running hash is on. No other fields are allowed.
+//
+
+use crate::{Hash, MerkleError, DEFAULT_MAX_PROOF_LEN, HASH_LEN};
+
+/// Which child of its parent a node is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    /// Convert a proof path entry, `true` meaning the right child
+    pub fn from_is_right(is_right: bool) -> Self {
+        if is_right { Side::Right } else { Side::Left }
+    }
+
+    /// Whether this is the right child, as a proof path entry
+    pub fn is_right(self) -> bool {
+        self == Side::Right
+    }
+}
+
+/// What a `true` entry in a boolean proof path means
+///
+/// This crate uses `CurrentIsRight`; implementations elsewhere sometimes
+/// record the sibling's side instead, which inverts every bit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PathConvention {
+    /// `true` means the running hash is the right child
+    #[default]
+    CurrentIsRight,
+    /// `true` means the sibling is the right child
+    SiblingIsRight,
+}
+
+impl PathConvention {
+    /// Convert a proof path in this convention to `CurrentIsRight`
+    pub fn normalize(self, proof_path: &[bool]) -> Vec<bool> {
+        match self {
+            PathConvention::CurrentIsRight => proof_path.to_vec(),
+            PathConvention::SiblingIsRight => proof_path.iter().map(|bit| !bit).collect(),
+        }
+    }
+}
+
+/// An inclusion proof: the sibling hashes from a leaf up to the root and,
+/// for each of them, which side of its parent the running hash is on
+///
+/// Proofs are plain data, `Send` and `Sync`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proof {
+    pub siblings: Vec<Hash>,
+    pub path: Vec<Side>,
+    pub leaf_index: usize,
+}
+
+impl Proof {
+    /// Create a proof from the siblings and boolean path `generate_proof`
+    /// returns for the leaf at `leaf_index`
+    pub fn new(leaf_index: usize, siblings: Vec<Hash>, proof_path: &[bool]) -> Self {
+        Proof {
+            siblings,
+            path: proof_path.iter().copied().map(Side::from_is_right).collect(),
+            leaf_index,
+        }
+    }
+
+    /// Get the path as booleans, `true` where the running hash is the right
+    /// child, as taken by `verify` and `verify_proof`
+    pub fn path_bits(&self) -> Vec<bool> {
+        self.path.iter().map(|side| side.is_right()).collect()
+    }
+
+    /// Format the proof as Solidity literals for a contract call: the
+    /// siblings as a `bytes32[]` of `0x`-prefixed hex, and the path as a
+    /// `bool[]` with `true` where the running hash is the right child
+    ///
+    /// Verifiers that sort each pair before hashing, like OpenZeppelin's
+    /// `MerkleProof`, only take the first. This crate's trees hash pairs in
+    /// order, so a contract checking their proofs needs the path too.
+    pub fn to_solidity(&self) -> (String, String) {
+        let siblings: Vec<String> = self.siblings.iter()
+            .map(|sibling| format!("\"0x{}\"", hex::encode(sibling)))
+            .collect();
+        let path: Vec<String> = self.path.iter()
+            .map(|side| side.is_right().to_string())
+            .collect();
+        (format!("[{}]", siblings.join(",")), format!("[{}]", path.join(",")))
+    }
+
+    /// Encode the proof as JSON in the schema described above
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        let json = ProofJson {
+            leaf_index: self.leaf_index,
+            siblings: self.siblings.iter().map(hex::encode).collect(),
+            path: self.path.clone(),
+        };
+        serde_json::to_string(&json).expect("proofs always serialize")
+    }
+
+    /// Parse a proof from JSON, rejecting anything that does not follow the
+    /// schema exactly: missing or extra fields, values of the wrong type, a
+    /// negative or oversized index, siblings that are not 64 lowercase hex
+    /// digits, or a path whose length differs from the siblings'
+    #[cfg(feature = "serde")]
+    pub fn from_json_strict(json: &str) -> Result<Self, MerkleError> {
+        let json: ProofJson = serde_json::from_str(json)
+            .map_err(|_| MerkleError::BadFormat("proof JSON does not match the schema"))?;
+        let bad_sibling = MerkleError::BadFormat("sibling is not 64 lowercase hex digits");
+        let siblings = json.siblings.iter()
+            .map(|sibling| {
+                let lowercase = sibling.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+                if sibling.len() != HASH_LEN * 2 || !lowercase {
+                    return Err(bad_sibling.clone());
+                }
+                hex::decode(sibling).map_err(|_| bad_sibling.clone())
+            })
+            .collect::<Result<_, _>>()?;
+
+        let proof = Proof { siblings, path: json.path, leaf_index: json.leaf_index };
+        proof.validate_shape()?;
+        Ok(proof)
+    }
+
+    /// Check that the proof is structurally well-formed before verifying it
+    ///
+    /// The siblings and path must have the same length, no longer than
+    /// `DEFAULT_MAX_PROOF_LEN`, and every sibling must be a `HASH_LEN`-byte
+    /// SHA-256 hash. This separates malformed input from a cryptographic
+    /// mismatch, which `verify` reports only as `false`.
+    pub fn validate_shape(&self) -> Result<(), MerkleError> {
+        if self.siblings.len() != self.path.len() {
+            return Err(MerkleError::PathLengthMismatch {
+                proof_len: self.siblings.len(),
+                path_len: self.path.len(),
+            });
+        }
+        if self.siblings.len() > DEFAULT_MAX_PROOF_LEN {
+            return Err(MerkleError::ProofTooLong {
+                len: self.siblings.len(),
+                max: DEFAULT_MAX_PROOF_LEN,
+            });
+        }
+        if let Some((index, sibling)) = self.siblings.iter()
+            .enumerate()
+            .find(|(_, sibling)| sibling.len() != HASH_LEN)
+        {
+            return Err(MerkleError::InvalidSiblingLength {
+                index,
+                len: sibling.len(),
+                expected: HASH_LEN,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The JSON form of a `Proof`
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProofJson {
+    leaf_index: usize,
+    siblings: Vec<String>,
+    path: Vec<Side>,
+}
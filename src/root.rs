@@ -0,0 +1,64 @@
+//
+// Fixed-size root hashes
+//
+// Roots read from binary protocols or configuration are better held as
+// exactly `HASH_LEN` bytes than as a `Vec<u8>` of any length, so a truncated
+// or padded root is rejected when it is parsed rather than failing every
+// verification later.
+//
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{MerkleError, HASH_LEN};
+
+/// A 32-byte SHA-256 root hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RootHash([u8; HASH_LEN]);
+
+impl RootHash {
+    /// Get the hash bytes, e.g. to pass to `verify_proof`
+    pub fn as_bytes(&self) -> &[u8; HASH_LEN] {
+        &self.0
+    }
+}
+
+impl From<[u8; HASH_LEN]> for RootHash {
+    fn from(bytes: [u8; HASH_LEN]) -> Self {
+        RootHash(bytes)
+    }
+}
+
+/// Read a root from raw bytes, failing unless there are exactly `HASH_LEN`
+impl TryFrom<&[u8]> for RootHash {
+    type Error = MerkleError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let bytes = bytes.try_into()
+            .map_err(|_| MerkleError::InvalidRootLength { len: bytes.len(), expected: HASH_LEN })?;
+        Ok(RootHash(bytes))
+    }
+}
+
+/// Parse a root from hex, in either case
+impl FromStr for RootHash {
+    type Err = MerkleError;
+
+    fn from_str(hex_root: &str) -> Result<Self, Self::Err> {
+        let bytes = hex::decode(hex_root).map_err(|_| MerkleError::BadFormat("root is not hex"))?;
+        RootHash::try_from(bytes.as_slice())
+    }
+}
+
+impl AsRef<[u8]> for RootHash {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Formats the root as lowercase hex
+impl fmt::Display for RootHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
@@ -0,0 +1,257 @@
+//
+// Binary serialization of Merkle Trees
+//
+// Version 1 stores only the leaf hashes and the hashing configuration;
+// internal nodes are recomputed on load. The layout is self-describing so
+// that a loader can reject blobs it does not understand instead of
+// misreading them:
+//
+//   magic          4 bytes  b"MRKL"
+//   version        1 byte   1, or 2 for trees stored with their nodes
+//   leaf hasher    1 byte   HashAlgorithm id, plus 0x80 for leaves hashed
+//                           with a length prefix
+//   node hasher    1 byte   HashAlgorithm id
+//   separator len  1 byte   0 when there is no node separator
+//   separator      separator len bytes
+//   hash len       1 byte   length of each leaf hash
+//   leaf count     8 bytes  little-endian
+//   leaf hashes    leaf count * hash len bytes
+//
+// Version 2 keeps every node so a loader can check the stored internal
+// hashes. After the hash len it has a node hash len byte (the length of each
+// internal hash), and after the leaf count, in place of the leaf hashes, the
+// nodes in pre-order as a tag byte (0 for a leaf, 1 for an internal node)
+// followed by the node's hash. Since the stored shape comes from untrusted
+// input, loading rejects any tree that `MerkleTree::new` could not have built.
+//
+
+use crate::{checked_split, HashAlgorithm, MerkleError, MerkleTree, Node, TreeHasher, MAX_LEAVES};
+
+const MAGIC: &[u8; 4] = b"MRKL";
+const VERSION: u8 = 1;
+const VERSION_WITH_NODES: u8 = 2;
+
+const LENGTH_PREFIXED: u8 = 0x80;
+
+const LEAF_TAG: u8 = 0;
+const INTERNAL_TAG: u8 = 1;
+
+impl<M> MerkleTree<M> {
+    /// Serialize the tree's leaf hashes and hashing configuration
+    ///
+    /// Every leaf hash must have the same length, which holds for any tree
+    /// built from data blocks. The key of an HMAC tree is not written, so a
+    /// keyed tree cannot be loaded back with `from_bytes`, and leaf metadata
+    /// is not written either.
+    ///
+    /// Returns `MerkleError::BadFormat` if the leaf hashes differ in length,
+    /// or if the node separator or the leaf hashes are over 255 bytes, which
+    /// the format cannot record.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MerkleError> {
+        let mut bytes = self.header(VERSION)?;
+        for leaf in self.leaves() {
+            bytes.extend_from_slice(leaf);
+        }
+        Ok(bytes)
+    }
+
+    /// Serialize every node of the tree along with the hashing configuration
+    ///
+    /// This is larger than `to_bytes`, but `from_bytes` checks the stored
+    /// internal hashes and shape when loading it instead of trusting the
+    /// leaves alone.
+    ///
+    /// Fails as `to_bytes` does, and also if the node hashes are over 255
+    /// bytes.
+    pub fn to_bytes_with_nodes(&self) -> Result<Vec<u8>, MerkleError> {
+        let mut bytes = self.header(VERSION_WITH_NODES)?;
+        let mut stack: Vec<&Node> = self.root.iter().collect();
+        while let Some(node) = stack.pop() {
+            match node.children() {
+                Some((left, right)) => {
+                    bytes.push(INTERNAL_TAG);
+                    stack.push(right);
+                    stack.push(left);
+                }
+                None => bytes.push(LEAF_TAG),
+            }
+            bytes.extend_from_slice(node.hash());
+        }
+        Ok(bytes)
+    }
+
+    /// Write every field up to and including the leaf count
+    fn header(&self, version: u8) -> Result<Vec<u8>, MerkleError> {
+        let separator = self.hasher.node_separator.as_deref().unwrap_or_default();
+        let hash_len = self.leaves().next().map_or(0, |leaf| leaf.len());
+        if self.leaves().any(|leaf| leaf.len() != hash_len) {
+            return Err(MerkleError::BadFormat("leaf hashes differ in length"));
+        }
+
+        let mut bytes = Vec::with_capacity(18 + separator.len() + self.leaf_count * hash_len);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(version);
+        let length_prefixed = if self.hasher.length_prefixed_leaves { LENGTH_PREFIXED } else { 0 };
+        bytes.push(self.hasher.leaf.id() | length_prefixed);
+        bytes.push(self.hasher.node.id());
+        bytes.push(byte_len(separator.len(), "node separator is over 255 bytes")?);
+        bytes.extend_from_slice(separator);
+        bytes.push(byte_len(hash_len, "leaf hashes are over 255 bytes")?);
+        if version == VERSION_WITH_NODES {
+            let node_hash_len = match self.root.as_ref().filter(|root| root.children().is_some()) {
+                Some(root) => root.hash.len(),
+                None => 0,
+            };
+            bytes.push(byte_len(node_hash_len, "node hashes are over 255 bytes")?);
+        }
+        bytes.extend_from_slice(&(self.leaf_count as u64).to_le_bytes());
+        Ok(bytes)
+    }
+}
+
+/// Fit a length into the single byte the header gives it
+fn byte_len(len: usize, reason: &'static str) -> Result<u8, MerkleError> {
+    u8::try_from(len).map_err(|_| MerkleError::BadFormat(reason))
+}
+
+impl MerkleTree {
+    /// Load a tree written by `to_bytes` or `to_bytes_with_nodes`
+    ///
+    /// Trees stored with only their leaves have their internal nodes rebuilt.
+    /// Trees stored with every node are rejected unless each internal hash
+    /// matches its children and the shape is the one `new` builds for that
+    /// many leaves, so a crafted blob cannot smuggle in a different tree
+    /// behind a valid root.
+    ///
+    /// Returns `MerkleError::BadFormat` if the magic header or version is
+    /// not recognised, the hash algorithm is unknown, the data is truncated
+    /// or has trailing bytes, or a stored tree fails those checks.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MerkleError> {
+        let mut reader = Reader(bytes);
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(MerkleError::BadFormat("missing magic header"));
+        }
+        let version = reader.byte()?;
+        if version != VERSION && version != VERSION_WITH_NODES {
+            return Err(MerkleError::BadFormat("unsupported version"));
+        }
+        let leaf_id = reader.byte()?;
+        let length_prefixed_leaves = leaf_id & LENGTH_PREFIXED != 0;
+        let leaf = HashAlgorithm::from_id(leaf_id & !LENGTH_PREFIXED)
+            .ok_or(MerkleError::BadFormat("unknown leaf hash algorithm"))?;
+        let node = HashAlgorithm::from_id(reader.byte()?)
+            .ok_or(MerkleError::BadFormat("unknown node hash algorithm"))?;
+        let separator_len = reader.byte()? as usize;
+        let separator = reader.take(separator_len)?;
+        let node_separator = (!separator.is_empty()).then(|| separator.to_vec());
+        let hash_len = reader.byte()? as usize;
+        let node_hash_len = match version {
+            VERSION_WITH_NODES => reader.byte()? as usize,
+            _ => 0,
+        };
+        let hasher = TreeHasher { leaf, node, node_separator, length_prefixed_leaves };
+
+        let leaf_count = u64::from_le_bytes(reader.take(8)?.try_into().expect("took 8 bytes"));
+        let leaf_count = usize::try_from(leaf_count)
+            .ok()
+            .filter(|&count| count <= MAX_LEAVES)
+            .ok_or(MerkleError::TooManyLeaves { max: MAX_LEAVES })?;
+        if leaf_count > 0 && hash_len == 0 {
+            return Err(MerkleError::BadFormat("leaf hashes are empty"));
+        }
+        if version == VERSION_WITH_NODES {
+            return Self::read_nodes(reader, hasher, leaf_count, hash_len, node_hash_len);
+        }
+
+        let expected_len = leaf_count.checked_mul(hash_len)
+            .ok_or(MerkleError::BadFormat("leaf hashes overflow"))?;
+        if reader.0.len() != expected_len {
+            return Err(MerkleError::BadFormat("leaf hash data has the wrong length"));
+        }
+
+        let nodes = reader.0.chunks(hash_len.max(1))
+            .map(|hash| Node::from_hash(hash.to_vec()))
+            .collect();
+
+        Ok(MerkleTree::build(nodes, hasher))
+    }
+
+    /// Load the nodes of a tree written by `to_bytes_with_nodes`
+    fn read_nodes(
+        mut reader: Reader,
+        hasher: TreeHasher,
+        leaf_count: usize,
+        hash_len: usize,
+        node_hash_len: usize,
+    ) -> Result<Self, MerkleError> {
+        let leaves_len = leaf_count.checked_mul(1 + hash_len);
+        let internal_len = leaf_count.saturating_sub(1).checked_mul(1 + node_hash_len);
+        let expected_len = leaves_len.zip(internal_len)
+            .and_then(|(leaves_len, internal_len)| leaves_len.checked_add(internal_len))
+            .ok_or(MerkleError::BadFormat("node data overflows"))?;
+        if reader.0.len() != expected_len {
+            return Err(MerkleError::BadFormat("node data has the wrong length"));
+        }
+
+        let mut tree = MerkleTree::build(Vec::new(), hasher);
+        if leaf_count > 0 {
+            let lengths = (hash_len, node_hash_len);
+            tree.root = Some(read_node(&mut reader, leaf_count, &tree.hasher, lengths)?);
+            tree.leaf_count = leaf_count;
+            tree.metadata = vec![(); leaf_count];
+        }
+        Ok(tree)
+    }
+}
+
+/// Read the node over `width` leaves, in the canonical shape, checking that
+/// each stored internal hash matches the one computed from its children
+///
+/// The shape is fixed by `width`, so recursion is no deeper than the tree's
+/// height however the input is crafted.
+fn read_node(
+    reader: &mut Reader,
+    width: usize,
+    hasher: &TreeHasher,
+    (hash_len, node_hash_len): (usize, usize),
+) -> Result<Node, MerkleError> {
+    let tag = reader.byte()?;
+    if tag != LEAF_TAG && tag != INTERNAL_TAG {
+        return Err(MerkleError::BadFormat("unknown node tag"));
+    }
+    if (tag == LEAF_TAG) != (width == 1) {
+        return Err(MerkleError::BadFormat("tree shape is not canonical for its leaf count"));
+    }
+    if width == 1 {
+        return Ok(Node::from_hash(reader.take(hash_len)?.to_vec()));
+    }
+
+    let stored_hash = reader.take(node_hash_len)?;
+    let left_width = checked_split(width).ok_or(MerkleError::TooManyLeaves { max: MAX_LEAVES })?;
+    let left = read_node(reader, left_width, hasher, (hash_len, node_hash_len))?;
+    let right = read_node(reader, width - left_width, hasher, (hash_len, node_hash_len))?;
+    let node = Node::new_parent(left, right, hasher);
+    if node.hash != stored_hash {
+        return Err(MerkleError::BadFormat("internal node hash does not match its children"));
+    }
+    Ok(node)
+}
+
+/// Reads fields from the front of a byte slice
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MerkleError> {
+        if self.0.len() < len {
+            return Err(MerkleError::BadFormat("unexpected end of data"));
+        }
+        let (field, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(field)
+    }
+
+    fn byte(&mut self) -> Result<u8, MerkleError> {
+        Ok(self.take(1)?[0])
+    }
+}
@@ -0,0 +1,55 @@
+//
+// Snapshots for undoing a batch of mutations
+//
+// A snapshot copies out everything a mutation can change: the nodes, the
+// per-leaf metadata and data, and what pruning kept. Nodes own their
+// children, so taking a snapshot copies the whole tree, O(n) in the number
+// of leaves; restoring moves it back in without copying.
+//
+
+use crate::{MerkleTree, Node, TreeHasher};
+
+/// The state of a `MerkleTree` at the time `MerkleTree::snapshot` was called
+#[derive(Debug, Clone)]
+pub struct Snapshot<M = ()> {
+    root: Option<Node>,
+    leaf_count: usize,
+    hasher: TreeHasher,
+    metadata: Vec<M>,
+    leaf_data: Option<Vec<Vec<u8>>>,
+    pruned_to: Option<Vec<usize>>,
+}
+
+impl<M> Snapshot<M> {
+    /// Get the root hash the tree had when the snapshot was taken
+    pub fn root_hash(&self) -> Option<&[u8]> {
+        self.root.as_ref().map(Node::hash)
+    }
+}
+
+impl<M: Clone> MerkleTree<M> {
+    /// Record the current state of the tree, to go back to with `restore`
+    pub fn snapshot(&self) -> Snapshot<M> {
+        Snapshot {
+            root: self.root.clone(),
+            leaf_count: self.leaf_count,
+            hasher: self.hasher.clone(),
+            metadata: self.metadata.clone(),
+            leaf_data: self.leaf_data.clone(),
+            pruned_to: self.pruned_to.clone(),
+        }
+    }
+
+    /// Return the tree to the state recorded in `snapshot`, discarding every
+    /// mutation since
+    ///
+    /// Registered callbacks stay, and are told the restored root like after
+    /// any other change.
+    pub fn restore(&mut self, snapshot: Snapshot<M>) {
+        self.hasher = snapshot.hasher;
+        self.metadata = snapshot.metadata;
+        self.leaf_data = snapshot.leaf_data;
+        self.pruned_to = snapshot.pruned_to;
+        self.set_root(snapshot.root, snapshot.leaf_count);
+    }
+}
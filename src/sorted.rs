@@ -0,0 +1,279 @@
+//
+// Sorted Merkle Tree with proofs of non-membership
+//
+// When the leaves are kept in strictly increasing order, a value can be shown
+// to be absent by proving the two adjacent leaves that would surround it.
+// If they are neighbours in the tree and the value falls strictly between
+// them, there is no room for it anywhere else. The same pair of neighbours
+// shows that a whole range holds no leaves, and when the range's bounds are
+// leaves themselves, it proves their membership too.
+//
+// A tree can also hold (key, value) entries sorted by key. Each leaf then
+// encodes both, as the key's length, the key and the value, so a neighbour
+// proves the value stored under its key, and the gaps are between keys.
+//
+
+use crate::{proof_path_for, verify_proof, MerkleError, MerkleTree, Proof};
+
+/// A Merkle Tree over strictly increasing leaves
+#[derive(Debug)]
+pub struct SortedMerkleTree {
+    leaves: Vec<Vec<u8>>,
+    keyed: bool,
+    tree: MerkleTree,
+}
+
+/// An inclusion proof for one leaf of a `SortedMerkleTree`, together with the
+/// data and position it proves
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NeighborProof {
+    pub index: usize,
+    pub data: Vec<u8>,
+    pub proof: Proof,
+}
+
+/// Proof that a value is not among the leaves of a `SortedMerkleTree`
+///
+/// Holds the closest leaf below the value and the closest leaf above it. One
+/// of them is missing when the value sorts before the first leaf or after the
+/// last one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbsenceProof {
+    pub left: Option<NeighborProof>,
+    pub right: Option<NeighborProof>,
+}
+
+impl SortedMerkleTree {
+    /// Create a sorted tree, rejecting leaves that are not strictly increasing
+    pub fn new(leaves: Vec<Vec<u8>>) -> Result<Self, MerkleError> {
+        if let Some(i) = leaves.windows(2).position(|pair| pair[0] >= pair[1]) {
+            return Err(MerkleError::UnsortedLeaves { index: i + 1 });
+        }
+
+        let tree = MerkleTree::new(leaves.clone());
+        Ok(SortedMerkleTree { leaves, keyed: false, tree })
+    }
+
+    /// Create a sorted tree over (key, value) entries, rejecting keys that
+    /// are not strictly increasing
+    ///
+    /// Each leaf is `encode_entry(key, value)`, and absence and gap proofs
+    /// are about the keys.
+    pub fn from_entries(entries: Vec<(Vec<u8>, Vec<u8>)>) -> Result<Self, MerkleError> {
+        if let Some(i) = entries.windows(2).position(|pair| pair[0].0 >= pair[1].0) {
+            return Err(MerkleError::UnsortedLeaves { index: i + 1 });
+        }
+
+        let leaves: Vec<Vec<u8>> = entries.iter()
+            .map(|(key, value)| encode_entry(key, value))
+            .collect();
+        let tree = MerkleTree::new(leaves.clone());
+        Ok(SortedMerkleTree { leaves, keyed: true, tree })
+    }
+
+    /// Get the root hash of the tree
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        self.tree.root_hash()
+    }
+
+    /// Get the number of leaves in the tree
+    pub fn leaf_count(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Get the underlying Merkle Tree, e.g. to prove membership
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    /// Prove that `value` is not a leaf of the tree
+    ///
+    /// Returns `None` if the value is present, or if the tree is empty and
+    /// so has no root to prove against.
+    pub fn prove_absence(&self, value: &[u8]) -> Option<AbsenceProof> {
+        if self.leaves.is_empty() {
+            return None;
+        }
+
+        let position = self.leaves.binary_search_by(|leaf| self.key(leaf).cmp(value)).err()?;
+        let left = position.checked_sub(1).map(|index| self.neighbor(index));
+        let right = (position < self.leaves.len()).then(|| self.neighbor(position));
+
+        Some(AbsenceProof { left, right })
+    }
+
+    /// Prove that no leaf lies strictly between `low` and `high`
+    ///
+    /// The proof holds the last leaf not above `low` and the first leaf not
+    /// below `high`, which are adjacent. When `low` and `high` are
+    /// consecutive leaves, those are `low` and `high` themselves, so one
+    /// proof shows both are present and nothing sorts between them.
+    ///
+    /// Returns `None` if some leaf does lie in the range, if `low` is not
+    /// below `high`, or if the tree is empty.
+    pub fn prove_range_gap(&self, low: &[u8], high: &[u8]) -> Option<AbsenceProof> {
+        if self.leaves.is_empty() || low >= high {
+            return None;
+        }
+
+        let position = self.leaves.partition_point(|leaf| self.key(leaf) <= low);
+        if self.leaves.get(position).is_some_and(|leaf| self.key(leaf) < high) {
+            return None;
+        }
+        let left = position.checked_sub(1).map(|index| self.neighbor(index));
+        let right = (position < self.leaves.len()).then(|| self.neighbor(position));
+
+        Some(AbsenceProof { left, right })
+    }
+
+    /// The key a leaf is sorted by: the whole leaf, or the key of an entry
+    fn key<'a>(&self, leaf: &'a [u8]) -> &'a [u8] {
+        if self.keyed {
+            decode_entry(leaf).expect("entry leaves are well formed").0
+        } else {
+            leaf
+        }
+    }
+
+    fn neighbor(&self, index: usize) -> NeighborProof {
+        NeighborProof {
+            index,
+            data: self.leaves[index].clone(),
+            proof: self.tree.proof(index).expect("neighbor index is within the tree"),
+        }
+    }
+
+    /// Verify that `value` is absent from the sorted tree with the given root
+    /// and leaf count
+    ///
+    /// Each neighbour must verify against the root at the position it claims,
+    /// the neighbours must be adjacent, and the value must sort strictly
+    /// between them. The leaf count has to come from the same trusted source
+    /// as the root, since it decides which positions are first and last.
+    pub fn verify_absence(
+        root: &[u8],
+        leaf_count: usize,
+        value: &[u8],
+        proof: &AbsenceProof,
+    ) -> bool {
+        verify_neighbors(root, leaf_count, proof, |left| left < value, |right| value < right)
+    }
+
+    /// Verify that no leaf lies strictly between `low` and `high` in the
+    /// sorted tree with the given root and leaf count
+    ///
+    /// The checks are those of `verify_absence`, except that the neighbours
+    /// may equal the bounds.
+    pub fn verify_range_gap(
+        root: &[u8],
+        leaf_count: usize,
+        low: &[u8],
+        high: &[u8],
+        proof: &AbsenceProof,
+    ) -> bool {
+        low < high
+            && verify_neighbors(root, leaf_count, proof, |left| left <= low, |right| high <= right)
+    }
+
+    /// Verify that no entry's key is `key` in the keyed sorted tree with the
+    /// given root and leaf count
+    ///
+    /// As `verify_absence`, comparing against the neighbours' keys.
+    pub fn verify_entry_absence(
+        root: &[u8],
+        leaf_count: usize,
+        key: &[u8],
+        proof: &AbsenceProof,
+    ) -> bool {
+        verify_neighbors(
+            root,
+            leaf_count,
+            proof,
+            |left| entry_key(left).is_some_and(|left| left < key),
+            |right| entry_key(right).is_some_and(|right| key < right),
+        )
+    }
+
+    /// Verify that no entry's key lies strictly between `low` and `high` in
+    /// the keyed sorted tree with the given root and leaf count
+    ///
+    /// As `verify_range_gap`, comparing against the neighbours' keys. When
+    /// the bounds are the neighbours' keys, this also proves the values
+    /// stored under them, which `decode_entry` reads from the proof.
+    pub fn verify_entry_range_gap(
+        root: &[u8],
+        leaf_count: usize,
+        low: &[u8],
+        high: &[u8],
+        proof: &AbsenceProof,
+    ) -> bool {
+        low < high
+            && verify_neighbors(
+                root,
+                leaf_count,
+                proof,
+                |left| entry_key(left).is_some_and(|left| left <= low),
+                |right| entry_key(right).is_some_and(|right| high <= right),
+            )
+    }
+}
+
+/// Encode a (key, value) entry as a leaf: the key's length as 8 big-endian
+/// bytes, then the key, then the value
+///
+/// The length prefix keeps entries with different splits of the same bytes
+/// apart, e.g. ("ab", "c") and ("a", "bc").
+pub fn encode_entry(key: &[u8], value: &[u8]) -> Vec<u8> {
+    [&(key.len() as u64).to_be_bytes(), key, value].concat()
+}
+
+/// Split a leaf made by `encode_entry` back into its key and value
+///
+/// Returns `None` if the leaf is too short for the length it declares.
+pub fn decode_entry(leaf: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (len, rest) = leaf.split_first_chunk::<8>()?;
+    let len = usize::try_from(u64::from_be_bytes(*len)).ok()?;
+    (len <= rest.len()).then(|| rest.split_at(len))
+}
+
+fn entry_key(leaf: &[u8]) -> Option<&[u8]> {
+    decode_entry(leaf).map(|(key, _)| key)
+}
+
+/// Verify that the neighbours in `proof` are adjacent leaves of the tree at
+/// the positions they claim, and that they sit on the expected sides of the
+/// gap according to `left_fits` and `right_fits`
+fn verify_neighbors(
+    root: &[u8],
+    leaf_count: usize,
+    proof: &AbsenceProof,
+    left_fits: impl Fn(&[u8]) -> bool,
+    right_fits: impl Fn(&[u8]) -> bool,
+) -> bool {
+    let proves_position = |neighbor: &NeighborProof| {
+        let path = neighbor.proof.path_bits();
+        neighbor.index < leaf_count
+            && neighbor.proof.leaf_index == neighbor.index
+            && path == proof_path_for(neighbor.index, leaf_count)
+            && verify_proof(root, &neighbor.data, &neighbor.proof.siblings, &path)
+    };
+
+    match (&proof.left, &proof.right) {
+        (Some(left), Some(right)) => {
+            left.index.checked_add(1) == Some(right.index)
+                && left_fits(&left.data)
+                && right_fits(&right.data)
+                && proves_position(left)
+                && proves_position(right)
+        }
+        (None, Some(right)) => {
+            right.index == 0 && right_fits(&right.data) && proves_position(right)
+        }
+        (Some(left), None) => {
+            left.index.checked_add(1) == Some(leaf_count)
+                && left_fits(&left.data)
+                && proves_position(left)
+        }
+        (None, None) => false,
+    }
+}
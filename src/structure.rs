@@ -0,0 +1,72 @@
+//
+// A common interface over the different tree representations
+//
+// The pointer-based `MerkleTree` and the flat `FrozenMerkleTree` store their
+// nodes differently but answer the same questions: what is the root, what is
+// the proof for a leaf, and does a proof hold. Code written against
+// `MerkleStructure` works with either, and with any variant added later.
+//
+
+use crate::{FrozenMerkleTree, MerkleTree};
+
+/// Operations shared by every Merkle Tree representation in this crate
+pub trait MerkleStructure {
+    /// Get the root hash, or `None` for an empty tree
+    fn root(&self) -> Option<&[u8]>;
+
+    /// Get the number of leaves
+    fn leaf_count(&self) -> usize;
+
+    /// Get the data block of the leaf at `index`, for trees that retain it
+    ///
+    /// Most trees keep only hashes, so this returns `None` unless overridden.
+    fn leaf_data(&self, _index: usize) -> Option<&[u8]> {
+        None
+    }
+
+    /// Generate an inclusion proof for the leaf at `index`
+    fn prove(&self, index: usize) -> Option<(Vec<Vec<u8>>, Vec<bool>)>;
+
+    /// Verify that a data block is part of the tree by providing a proof
+    fn verify(&self, data: &[u8], proof: &[Vec<u8>], proof_path: &[bool]) -> bool;
+}
+
+impl<M> MerkleStructure for MerkleTree<M> {
+    fn root(&self) -> Option<&[u8]> {
+        MerkleTree::root(self)
+    }
+
+    fn leaf_count(&self) -> usize {
+        MerkleTree::leaf_count(self)
+    }
+
+    fn leaf_data(&self, index: usize) -> Option<&[u8]> {
+        MerkleTree::leaf_data(self, index)
+    }
+
+    fn prove(&self, index: usize) -> Option<(Vec<Vec<u8>>, Vec<bool>)> {
+        self.generate_proof(index)
+    }
+
+    fn verify(&self, data: &[u8], proof: &[Vec<u8>], proof_path: &[bool]) -> bool {
+        MerkleTree::verify(self, data, proof, proof_path)
+    }
+}
+
+impl MerkleStructure for FrozenMerkleTree {
+    fn root(&self) -> Option<&[u8]> {
+        self.hashes.last().map(|hash| hash.as_slice())
+    }
+
+    fn leaf_count(&self) -> usize {
+        FrozenMerkleTree::leaf_count(self)
+    }
+
+    fn prove(&self, index: usize) -> Option<(Vec<Vec<u8>>, Vec<bool>)> {
+        self.generate_proof(index)
+    }
+
+    fn verify(&self, data: &[u8], proof: &[Vec<u8>], proof_path: &[bool]) -> bool {
+        FrozenMerkleTree::verify(self, data, proof, proof_path)
+    }
+}
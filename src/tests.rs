@@ -1,40 +1,2518 @@
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_merkle_root_consistency() {
-        let data1 = vec!["a", "b", "c", "d"];
-        let tree1 = MerkleTree::new(data1);
-
-        let data2 = vec!["a", "b", "c", "d"];
-        let tree2 = MerkleTree::new(data2);
-
-        assert_eq!(tree1.root(), tree2.root());
-    }
-
-    #[test]
-    fn test_merkle_root_changes_on_data_change() {
-        let data1 = vec!["a", "b", "c", "d"];
-        let tree1 = MerkleTree::new(data1);
-
-        let data2 = vec!["a", "b", "x", "d"];
-        let tree2 = MerkleTree::new(data2);
-
-        assert_ne!(tree1.root(), tree2.root());
-    }
-
-    #[test]
-    fn test_merkle_root_with_odd_number_of_leaves() {
-        let data = vec!["a", "b", "c"];
-        let tree = MerkleTree::new(data);
-        assert!(!tree.root().is_empty());
-    }
-
-    #[test]
-    fn test_single_element_merkle_tree() {
-        let data = vec!["only"];
-        let tree = MerkleTree::new(data);
-        assert_eq!(tree.root(), &hash("only"));
-    }
-}
\ No newline at end of file
+use super::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::borrow::Cow;
+use std::cell::Cell;
+
+fn blocks(data: &[&str]) -> Vec<Vec<u8>> {
+    data.iter().map(|d| d.as_bytes().to_vec()).collect()
+}
+
+fn hash(data: &str) -> Vec<u8> {
+    Sha256::digest(data.as_bytes()).to_vec()
+}
+
+fn hash_pair(left: &[u8], right: &[u8]) -> Vec<u8> {
+    Sha256::digest([left, right].concat()).to_vec()
+}
+
+/// Counts the allocations made on each thread, so a test can measure the
+/// code it runs while other tests run alongside
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static LIVE_BYTES: Cell<isize> = const { Cell::new(0) };
+    static PEAK_BYTES: Cell<isize> = const { Cell::new(0) };
+}
+
+fn track(delta: isize) {
+    let live = LIVE_BYTES.get() + delta;
+    LIVE_BYTES.set(live);
+    PEAK_BYTES.set(PEAK_BYTES.get().max(live));
+}
+
+// SAFETY: every call is forwarded unchanged to the system allocator
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.set(ALLOCATIONS.get() + 1);
+        track(layout.size() as isize);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        track(-(layout.size() as isize));
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.set(ALLOCATIONS.get() + 1);
+        track(new_size as isize - layout.size() as isize);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Run `f`, returning its result with the number of allocations it made and
+/// the most heap memory it held at once, on this thread
+fn measure_allocations<R>(f: impl FnOnce() -> R) -> (R, usize, usize) {
+    let (allocations, live) = (ALLOCATIONS.get(), LIVE_BYTES.get());
+    PEAK_BYTES.set(live);
+    let result = f();
+    (result, ALLOCATIONS.get() - allocations, (PEAK_BYTES.get() - live) as usize)
+}
+
+/// The root of a tree over `leaves`, computed one leaf at a time holding
+/// only the roots of the completed perfect subtrees
+fn streaming_root(leaves: impl Iterator<Item = Vec<u8>>) -> Option<Vec<u8>> {
+    let mut peaks: Vec<(u32, Vec<u8>)> = Vec::new();
+    for leaf in leaves {
+        let mut peak = (0, Sha256::digest(&leaf).to_vec());
+        while let Some((height, _)) = peaks.last()
+            && *height == peak.0
+        {
+            let (height, left) = peaks.pop().unwrap();
+            peak = (height + 1, hash_pair(&left, &peak.1));
+        }
+        peaks.push(peak);
+    }
+    peaks.into_iter()
+        .map(|(_, hash)| hash)
+        .rev()
+        .reduce(|right, left| hash_pair(&left, &right))
+}
+
+#[test]
+fn test_merkle_root_consistency() {
+    let data1 = blocks(&["a", "b", "c", "d"]);
+    let tree1 = MerkleTree::new(data1);
+
+    let data2 = blocks(&["a", "b", "c", "d"]);
+    let tree2 = MerkleTree::new(data2);
+
+    assert_eq!(tree1.root_hash(), tree2.root_hash());
+}
+
+#[test]
+fn test_merkle_root_changes_on_data_change() {
+    let data1 = blocks(&["a", "b", "c", "d"]);
+    let tree1 = MerkleTree::new(data1);
+
+    let data2 = blocks(&["a", "b", "x", "d"]);
+    let tree2 = MerkleTree::new(data2);
+
+    assert_ne!(tree1.root_hash(), tree2.root_hash());
+}
+
+#[test]
+fn test_merkle_root_with_odd_number_of_leaves() {
+    let data = blocks(&["a", "b", "c"]);
+    let tree = MerkleTree::new(data);
+    assert!(!tree.root_hash().unwrap().is_empty());
+}
+
+#[test]
+fn test_single_element_merkle_tree() {
+    let data = blocks(&["only"]);
+    let tree = MerkleTree::new(data);
+    assert_eq!(tree.root_hash(), Some(hash("only")));
+}
+
+#[test]
+fn test_nested_tree_composed_proofs() {
+    let shards: Vec<Vec<Vec<u8>>> = (0..4)
+        .map(|s| (0..4).map(|i| format!("shard {} leaf {}", s, i).into_bytes()).collect())
+        .collect();
+    let subtrees: Vec<MerkleTree> = shards.iter().cloned().map(MerkleTree::new).collect();
+    let top = MerkleTree::from_subtrees(&subtrees);
+    let top_root = top.root_hash().unwrap();
+
+    for (s, subtree) in subtrees.iter().enumerate() {
+        let (root_proof, root_path) = top.generate_proof(s).unwrap();
+        for (i, data) in shards[s].iter().enumerate() {
+            let (leaf_proof, leaf_path) = subtree.generate_proof(i).unwrap();
+            let (proof, proof_path) =
+                MerkleTree::compose_proofs(&leaf_proof, &leaf_path, &root_proof, &root_path);
+            assert!(top.verify(data, &proof, &proof_path));
+            assert!(!top.verify(b"not a leaf", &proof, &proof_path));
+            assert!(verify_proof(&top_root, data, &proof, &proof_path));
+        }
+    }
+
+    // Equal power-of-two shards compose into the same root as one flat tree
+    let flat = MerkleTree::new(shards.concat());
+    assert_eq!(top.root_hash(), flat.root_hash());
+}
+
+#[test]
+fn test_composed_proofs_through_a_promoted_shard() {
+    // With five shards the last is promoted twice, so proofs through it are
+    // shorter than the top tree is high
+    let shards: Vec<Vec<Vec<u8>>> = (0..5)
+        .map(|s| (0..2).map(|i| format!("shard {} leaf {}", s, i).into_bytes()).collect())
+        .collect();
+    let subtrees: Vec<MerkleTree> = shards.iter().cloned().map(MerkleTree::new).collect();
+    let top = MerkleTree::from_subtrees(&subtrees);
+
+    for (s, subtree) in subtrees.iter().enumerate() {
+        let (root_proof, root_path) = top.generate_proof(s).unwrap();
+        for (i, data) in shards[s].iter().enumerate() {
+            let (leaf_proof, leaf_path) = subtree.generate_proof(i).unwrap();
+            let (proof, proof_path) =
+                MerkleTree::compose_proofs(&leaf_proof, &leaf_path, &root_proof, &root_path);
+            assert!(top.verify(data, &proof, &proof_path));
+            assert_eq!(top.try_verify(data, &proof, &proof_path), Ok(true));
+            assert!(top.verify_constant_time(data, &proof, &proof_path));
+        }
+    }
+}
+
+#[test]
+fn test_iterate_leaves_in_insertion_order() {
+    let data = blocks(&["a", "b", "c", "d", "e"]);
+    let tree = MerkleTree::new(data.clone());
+
+    let mut leaves = Vec::new();
+    for leaf in &tree {
+        leaves.push(leaf.to_vec());
+    }
+
+    let expected: Vec<Vec<u8>> = data.iter().map(|d| Sha256::digest(d).to_vec()).collect();
+    assert_eq!(leaves, expected);
+    assert_eq!(tree.leaves().count(), tree.leaf_count());
+}
+
+/// All orderings of `items`, generated with Heap's algorithm
+fn permutations(items: &[Vec<u8>]) -> Vec<Vec<Vec<u8>>> {
+    fn heap(k: usize, items: &mut Vec<Vec<u8>>, out: &mut Vec<Vec<Vec<u8>>>) {
+        if k <= 1 {
+            out.push(items.clone());
+            return;
+        }
+        heap(k - 1, items, out);
+        for i in 0..k - 1 {
+            if k.is_multiple_of(2) {
+                items.swap(i, k - 1);
+            } else {
+                items.swap(0, k - 1);
+            }
+            heap(k - 1, items, out);
+        }
+    }
+
+    let mut items = items.to_vec();
+    let mut out = Vec::new();
+    heap(items.len(), &mut items, &mut out);
+    out
+}
+
+#[test]
+fn test_every_leaf_permutation_changes_root() {
+    // Sizes up to 6 cover promotion at one level (3, 5, 6) and at two levels (5)
+    for n in 2..=6 {
+        let data: Vec<Vec<u8>> = (0..n).map(|i| format!("leaf {}", i).into_bytes()).collect();
+        let orderings = permutations(&data);
+
+        let roots: std::collections::HashSet<Vec<u8>> = orderings.into_iter()
+            .map(|ordering| MerkleTree::new(ordering).root_hash().unwrap())
+            .collect();
+
+        let factorial: usize = (1..=n).product();
+        assert_eq!(roots.len(), factorial, "root collision among permutations of {} leaves", n);
+    }
+}
+
+#[test]
+fn test_promoted_node_is_not_duplicated() {
+    // Promoting the odd node (rather than pairing it with itself) keeps
+    // [a, b, c] distinct from [a, b, c, c]
+    let odd = MerkleTree::new(blocks(&["a", "b", "c"]));
+    let padded = MerkleTree::new(blocks(&["a", "b", "c", "c"]));
+    assert_ne!(odd.root_hash(), padded.root_hash());
+}
+
+#[test]
+fn test_hex_proof_decodes_and_verifies() {
+    let data = blocks(&["a", "b", "c", "d", "e"]);
+    let tree = MerkleTree::new(data.clone());
+    assert_eq!(tree.root_hex(), tree.root_hash().map(hex::encode).as_deref());
+
+    for (i, block) in data.iter().enumerate() {
+        let (hex_proof, proof_path) = tree.proof_hex(i).unwrap();
+        let proof: Vec<Vec<u8>> = hex_proof.iter().map(|h| hex::decode(h).unwrap()).collect();
+        assert_eq!(Some((proof.clone(), proof_path.clone())), tree.generate_proof(i));
+        assert!(tree.verify(block, &proof, &proof_path));
+    }
+    assert!(tree.proof_hex(data.len()).is_none());
+}
+
+#[test]
+fn test_hybrid_leaf_and_node_hashers() {
+    let data = blocks(&["a", "b", "c"]);
+    let tree = MerkleTree::builder()
+        .leaf_hasher(HashAlgorithm::Sha256)
+        .node_hasher(HashAlgorithm::Sha512)
+        .build(data.clone());
+
+    // Leaves are hashed once with SHA-256, every internal level with SHA-512
+    let ab = Sha512::digest([hash("a"), hash("b")].concat()).to_vec();
+    let expected = Sha512::digest([ab, hash("c")].concat()).to_vec();
+    assert_eq!(tree.root_hash(), Some(expected));
+    assert_ne!(tree.root_hash(), MerkleTree::new(data.clone()).root_hash());
+
+    for (i, block) in data.iter().enumerate() {
+        let (proof, proof_path) = tree.generate_proof(i).unwrap();
+        assert!(tree.verify(block, &proof, &proof_path));
+    }
+}
+
+#[cfg(feature = "blake3")]
+#[test]
+fn test_sha256_leaves_with_blake3_nodes() {
+    let data = blocks(&["a", "b", "c", "d"]);
+    let build = || MerkleTree::builder()
+        .leaf_hasher(HashAlgorithm::Sha256)
+        .node_hasher(HashAlgorithm::Blake3)
+        .build(data.clone());
+    let tree = build();
+    assert_eq!(tree.root_hash(), build().root_hash());
+
+    let ab = blake3::hash(&[hash("a"), hash("b")].concat());
+    let cd = blake3::hash(&[hash("c"), hash("d")].concat());
+    let expected = blake3::hash(&[*ab.as_bytes(), *cd.as_bytes()].concat());
+    assert_eq!(tree.root_hash(), Some(expected.as_bytes().to_vec()));
+
+    for (i, block) in data.iter().enumerate() {
+        let (proof, proof_path) = tree.generate_proof(i).unwrap();
+        assert!(tree.verify(block, &proof, &proof_path));
+    }
+}
+
+#[test]
+fn test_verify_leaf_hash_agrees_with_verify() {
+    let data = blocks(&["a", "b", "c", "d", "e"]);
+    let tree = MerkleTree::new(data.clone());
+
+    for (i, block) in data.iter().enumerate() {
+        let (proof, proof_path) = tree.generate_proof(i).unwrap();
+        let leaf_hash = Sha256::digest(block).to_vec();
+        assert!(tree.verify(block, &proof, &proof_path));
+        assert!(tree.verify_leaf_hash(&leaf_hash, &proof, &proof_path));
+
+        // The raw data is not a valid leaf hash
+        assert!(!tree.verify_leaf_hash(block, &proof, &proof_path));
+    }
+}
+
+#[test]
+fn test_oversized_proof_rejected_before_hashing() {
+    let tree = MerkleTree::new(blocks(&["a", "b", "c", "d"]));
+    let proof = vec![vec![0u8; 32]; 10_000];
+    let proof_path = vec![false; 10_000];
+
+    assert_eq!(
+        tree.try_verify(b"a", &proof, &proof_path),
+        Err(MerkleError::ProofTooLong { len: 10_000, max: DEFAULT_MAX_PROOF_LEN })
+    );
+    assert!(!tree.verify(b"a", &proof, &proof_path));
+}
+
+#[test]
+fn test_configured_max_proof_len() {
+    let data = blocks(&["a", "b", "c", "d", "e", "f", "g", "h"]);
+    let strict = MerkleTree::builder().max_proof_len(2).build(data.clone());
+    let (proof, proof_path) = strict.generate_proof(0).unwrap();
+    assert_eq!(proof.len(), 3);
+
+    assert_eq!(
+        strict.try_verify(&data[0], &proof, &proof_path),
+        Err(MerkleError::ProofTooLong { len: 3, max: 2 })
+    );
+    assert_eq!(MerkleTree::new(data.clone()).try_verify(&data[0], &proof, &proof_path), Ok(true));
+}
+
+#[test]
+fn test_proof_implied_size_range() {
+    assert_eq!(proof_implied_size_range(&[false, true, false]), (5, 8));
+    assert_eq!(proof_implied_size_range(&[]), (1, 1));
+    assert_eq!(proof_implied_size_range(&[false; 64]), ((1 << 63) + 1, usize::MAX));
+    assert_eq!(proof_implied_size_range(&[false; 100]), (usize::MAX, usize::MAX));
+
+    // Promoted leaves have short paths ending in a right turn, in trees of
+    // any size above the minimum
+    let last_of_five = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]).generate_proof(4).unwrap();
+    assert_eq!(last_of_five.1, vec![true]);
+    assert_eq!(proof_implied_size_range(&last_of_five.1), (2, usize::MAX));
+    let last_of_six = MerkleTree::new((0..6).map(|i| vec![i]).collect()).generate_proof(4).unwrap();
+    assert_eq!(last_of_six.1, vec![false, true]);
+    assert_eq!(proof_implied_size_range(&last_of_six.1), (3, usize::MAX));
+
+    for n in 1..=70 {
+        let tree = MerkleTree::new((0..n).map(|i| vec![i as u8]).collect());
+        for index in 0..n {
+            let (_, proof_path) = tree.generate_proof(index).unwrap();
+            let (min, max) = proof_implied_size_range(&proof_path);
+            assert!((min..=max).contains(&n), "leaf {} of {} outside {}..={}", index, n, min, max);
+        }
+    }
+}
+
+#[test]
+fn test_node_separator_changes_root_and_verifies() {
+    let data = blocks(&["a", "b", "c"]);
+    let plain = MerkleTree::new(data.clone());
+    let separated = MerkleTree::builder().node_separator(vec![0x00]).build(data.clone());
+    assert_ne!(plain.root_hash(), separated.root_hash());
+
+    let ab = Sha256::digest([hash("a"), vec![0x00], hash("b")].concat()).to_vec();
+    let expected = Sha256::digest([ab, vec![0x00], hash("c")].concat()).to_vec();
+    assert_eq!(separated.root_hash(), Some(expected));
+
+    for (i, block) in data.iter().enumerate() {
+        let (proof, proof_path) = separated.generate_proof(i).unwrap();
+        assert!(separated.verify(block, &proof, &proof_path));
+    }
+}
+
+#[test]
+fn test_from_reader_lines() {
+    let expected = MerkleTree::new(blocks(&["first", "second", "third"]));
+
+    let tree = MerkleTree::from_reader_lines(&b"first\nsecond\nthird"[..]).unwrap();
+    assert_eq!(tree.leaf_count(), 3);
+    assert_eq!(tree.root_hash(), expected.root_hash());
+
+    let trailing = MerkleTree::from_reader_lines(&b"first\r\nsecond\r\nthird\r\n"[..]).unwrap();
+    assert_eq!(trailing.leaf_count(), 3);
+    assert_eq!(trailing.root_hash(), expected.root_hash());
+
+    let empty = MerkleTree::from_reader_lines(&b""[..]).unwrap();
+    assert_eq!(empty.leaf_count(), 0);
+    assert_eq!(empty.root_hash(), None);
+}
+
+#[test]
+fn test_proof_shape_validation() {
+    let tree = MerkleTree::new(blocks(&["a", "b", "c", "d"]));
+    let mut proof = tree.proof(1).unwrap();
+    assert_eq!(proof.validate_shape(), Ok(()));
+
+    proof.siblings[1].pop();
+    assert_eq!(
+        proof.validate_shape(),
+        Err(MerkleError::InvalidSiblingLength { index: 1, len: 31, expected: HASH_LEN })
+    );
+
+    proof.path.pop();
+    assert_eq!(
+        proof.validate_shape(),
+        Err(MerkleError::PathLengthMismatch { proof_len: 2, path_len: 1 })
+    );
+}
+
+#[test]
+fn test_from_strs_matches_byte_constructor() {
+    let from_strs = MerkleTree::from_strs(&["a", "b", "c"]);
+    let from_bytes = MerkleTree::new(vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+    assert_eq!(from_strs.root_hash(), from_bytes.root_hash());
+
+    let (proof, proof_path) = from_strs.generate_proof(2).unwrap();
+    assert_eq!(from_bytes.generate_proof(2), Some((proof.clone(), proof_path.clone())));
+    assert!(from_bytes.verify(b"c", &proof, &proof_path));
+}
+
+#[test]
+fn test_root_hex_reversed_matches_bitcoin_display() {
+    // Block 100000 holds four transactions. Explorers show its txids and
+    // Merkle root in reversed byte order.
+    let displayed_txids = [
+        "8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+        "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+        "6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+        "e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+    ];
+    let displayed_root = "f3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766";
+    let txids: Vec<Vec<u8>> = displayed_txids.iter()
+        .map(|txid| hex::decode(txid).unwrap().into_iter().rev().collect())
+        .collect();
+
+    let tree = MerkleTree::builder()
+        .node_hasher(HashAlgorithm::DoubleSha256)
+        .build_from_hashes(txids.clone());
+    assert_eq!(tree.root_hex_reversed().as_deref(), Some(displayed_root));
+    let mut natural = hex::decode(displayed_root).unwrap();
+    natural.reverse();
+    assert_eq!(tree.root_hash(), Some(natural));
+    let (proof, proof_path) = tree.generate_proof(2).unwrap();
+    assert!(tree.verify_leaf_hash(&txids[2], &proof, &proof_path));
+    assert_eq!(MerkleTree::new(Vec::new()).root_hex_reversed(), None);
+}
+
+#[test]
+fn test_sorted_tree_proves_absence() {
+    let fruit = blocks(&["apple", "banana", "cherry", "date", "fig"]);
+    let sorted = SortedMerkleTree::new(fruit).unwrap();
+    let root = sorted.root_hash().unwrap();
+    let count = sorted.leaf_count();
+
+    let proof = sorted.prove_absence(b"coconut").unwrap();
+    assert_eq!(proof.left.as_ref().map(|n| n.index), Some(2));
+    assert_eq!(proof.right.as_ref().map(|n| n.index), Some(3));
+    assert!(SortedMerkleTree::verify_absence(&root, count, b"coconut", &proof));
+
+    // The same neighbours do not prove absence of values outside their gap
+    assert!(!SortedMerkleTree::verify_absence(&root, count, b"cherry", &proof));
+    assert!(!SortedMerkleTree::verify_absence(&root, count, b"elderberry", &proof));
+
+    // Values before the first leaf and after the last have one neighbour
+    let before = sorted.prove_absence(b"aardvark").unwrap();
+    assert!(before.left.is_none());
+    assert!(SortedMerkleTree::verify_absence(&root, count, b"aardvark", &before));
+    let after = sorted.prove_absence(b"grape").unwrap();
+    assert!(after.right.is_none());
+    assert!(SortedMerkleTree::verify_absence(&root, count, b"grape", &after));
+
+    assert!(sorted.prove_absence(b"date").is_none());
+}
+
+#[test]
+fn test_sorted_tree_rejects_non_adjacent_neighbors() {
+    let sorted = SortedMerkleTree::new(blocks(&["a", "c", "e", "g"])).unwrap();
+    let root = sorted.root_hash().unwrap();
+
+    // Leaves 0 and 2 both verify, but "c" sits between them
+    let gap = AbsenceProof {
+        left: sorted.prove_absence(b"b").unwrap().left,
+        right: sorted.prove_absence(b"d").unwrap().right,
+    };
+    assert!(!SortedMerkleTree::verify_absence(&root, 4, b"c", &gap));
+
+    assert_eq!(
+        SortedMerkleTree::new(blocks(&["a", "c", "b"])).unwrap_err(),
+        MerkleError::UnsortedLeaves { index: 2 }
+    );
+}
+
+#[test]
+fn test_root_change_callback_fires_per_mutation() {
+    use std::sync::{Arc, Mutex};
+
+    let mut tree = MerkleTree::from_strs(&["a"]);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let recorder = Arc::clone(&seen);
+    tree.on_root_change(move |root| recorder.lock().unwrap().push(root.to_vec()));
+
+    let mut expected = Vec::new();
+    let mut data = vec!["a"];
+    for next in ["b", "c", "d"] {
+        tree.push(next.as_bytes());
+        data.push(next);
+        expected.push(MerkleTree::from_strs(&data).root_hash().unwrap());
+    }
+    assert_eq!(*seen.lock().unwrap(), expected);
+
+    tree.update_leaf(0, b"z").unwrap();
+    tree.delete_leaf(3).unwrap();
+    let roots = seen.lock().unwrap();
+    assert_eq!(roots.len(), 5);
+    assert_eq!(roots.last(), MerkleTree::from_strs(&["z", "b", "c"]).root_hash().as_ref());
+}
+
+#[test]
+fn test_mutations_match_fresh_builds() {
+    let mut tree = MerkleTree::from_strs(&["a", "b", "c"]);
+    tree.push(b"d");
+    assert_eq!(tree.root_hash(), MerkleTree::from_strs(&["a", "b", "c", "d"]).root_hash());
+    tree.update_leaf(1, b"x").unwrap();
+    assert_eq!(tree.root_hash(), MerkleTree::from_strs(&["a", "x", "c", "d"]).root_hash());
+    tree.delete_leaf(0).unwrap();
+    assert_eq!(tree.root_hash(), MerkleTree::from_strs(&["x", "c", "d"]).root_hash());
+    assert_eq!(tree.leaf_count(), 3);
+
+    assert_eq!(
+        tree.update_leaf(3, b"y"),
+        Err(MerkleError::IndexOutOfRange { index: 3, leaf_count: 3 })
+    );
+}
+
+#[cfg(feature = "blake3")]
+#[test]
+fn test_blake3_tree() {
+    let data = blocks(&["a", "b", "c"]);
+    let tree = MerkleTree::new_blake3(data.clone());
+
+    let leaf = |data: &[u8]| *blake3::hash(data).as_bytes();
+    let ab = blake3::hash(&[leaf(b"a"), leaf(b"b")].concat());
+    let expected = blake3::hash(&[*ab.as_bytes(), leaf(b"c")].concat());
+    assert_eq!(tree.root_hash(), Some(expected.as_bytes().to_vec()));
+
+    for (i, block) in data.iter().enumerate() {
+        let (proof, proof_path) = tree.generate_proof(i).unwrap();
+        assert!(tree.verify(block, &proof, &proof_path));
+    }
+}
+
+#[test]
+fn test_from_vec_conversion() {
+    let data = blocks(&["a", "b", "c"]);
+    let tree: MerkleTree = data.clone().into();
+    assert_eq!(tree.root_hash(), MerkleTree::new(data).root_hash());
+
+    let empty: MerkleTree = Vec::<Vec<u8>>::new().into();
+    assert_eq!(empty.leaf_count(), 0);
+    assert_eq!(empty.root_hash(), None);
+}
+
+#[test]
+fn test_multiproof_covered_indices() {
+    let data = blocks(&["a", "b", "c", "d", "e", "f", "g"]);
+    let tree = MerkleTree::new(data.clone());
+
+    let indices = [1, 4, 6];
+    let proof = tree.multiproof(&indices).unwrap();
+    assert_eq!(proof.covered_indices(), &indices);
+
+    let covered: Vec<&[u8]> = indices.iter().map(|&i| data[i].as_slice()).collect();
+    assert!(tree.verify_multiproof(&covered, &proof));
+
+    // Claiming the same proof covers a different leaf fails
+    let swapped: Vec<&[u8]> = vec![&data[1], &data[5], &data[6]];
+    assert!(!tree.verify_multiproof(&swapped, &proof));
+    assert!(tree.multiproof(&[2, 7]).is_none());
+}
+
+#[test]
+fn test_multiproof_verifies_every_subset() {
+    for n in 1..=9usize {
+        let data: Vec<Vec<u8>> = (0..n).map(|i| vec![i as u8]).collect();
+        let tree = MerkleTree::new(data.clone());
+        for mask in 1..(1u32 << n) {
+            let indices: Vec<usize> = (0..n).filter(|i| mask & (1 << i) != 0).collect();
+            let proof = tree.multiproof(&indices).unwrap();
+            let covered: Vec<&[u8]> = indices.iter().map(|&i| data[i].as_slice()).collect();
+            assert!(tree.verify_multiproof(&covered, &proof), "{} leaves, {:?}", n, indices);
+        }
+    }
+}
+
+#[test]
+fn test_proofs_verify_with_multi_level_promotion() {
+    for n in [3, 5, 6, 7, 9, 11] {
+        let data: Vec<Vec<u8>> = (0..n).map(|i| format!("leaf {}", i).into_bytes()).collect();
+        let tree = MerkleTree::new(data.clone());
+
+        for (i, block) in data.iter().enumerate() {
+            let (proof, proof_path) = tree.generate_proof(i).unwrap();
+            assert!(tree.verify(block, &proof, &proof_path), "leaf {} of {}", i, n);
+            assert!(!tree.verify(b"other", &proof, &proof_path), "leaf {} of {}", i, n);
+        }
+    }
+
+    // The last of 11 leaves is promoted twice, so it only pairs at two levels
+    let tree = MerkleTree::new((0..11u8).map(|i| vec![i]).collect());
+    let (proof, proof_path) = tree.generate_proof(10).unwrap();
+    assert_eq!(proof.len(), 2);
+    assert_eq!(proof_path, vec![true, true]);
+}
+
+#[test]
+fn test_audit_proof_aliases() {
+    let data = blocks(&["a", "b", "c", "d", "e"]);
+    let tree = MerkleTree::new(data.clone());
+
+    for (i, block) in data.iter().enumerate() {
+        let (proof, proof_path) = tree.audit_proof(i).unwrap();
+        assert_eq!(tree.generate_proof(i), Some((proof.clone(), proof_path.clone())));
+        assert!(tree.verify_audit_proof(block, &proof, &proof_path));
+        assert!(!tree.verify_audit_proof(b"x", &proof, &proof_path));
+    }
+    assert_eq!(tree.audit_proof(5), None);
+}
+
+#[test]
+fn test_external_builder_matches_in_memory_root() {
+    for n in [0, 1, 2, 7, 1000] {
+        let data: Vec<Vec<u8>> = (0..n).map(|i: u32| i.to_le_bytes().to_vec()).collect();
+        let mut builder = ExternalMerkleBuilder::new().unwrap();
+        for block in &data {
+            builder.push(block).unwrap();
+        }
+        assert_eq!(builder.leaf_count(), n as usize);
+        assert_eq!(builder.finish().unwrap(), MerkleTree::new(data).root_hash());
+    }
+}
+
+/// Build a tree of `n` leaves with an `ExternalMerkleBuilder`, checking its
+/// root against one computed incrementally and that the build never held
+/// more than `memory_ceiling` bytes of heap, and return the most it held
+fn check_external_build(n: u32, memory_ceiling: usize) -> usize {
+    let leaves = || (0..n).map(|i| i.to_le_bytes().to_vec());
+    let (root, _, peak) = measure_allocations(|| {
+        let mut builder = ExternalMerkleBuilder::new().unwrap();
+        for i in 0..n {
+            builder.push(&i.to_le_bytes()).unwrap();
+        }
+        builder.finish().unwrap()
+    });
+
+    assert_eq!(root, streaming_root(leaves()));
+    assert!(peak < memory_ceiling, "external build of {} leaves held {} bytes", n, peak);
+    peak
+}
+
+#[test]
+fn test_external_builder_memory_stays_bounded() {
+    // Ten times the leaves take no more memory
+    let small = check_external_build(10_000, 64 * 1024);
+    let large = check_external_build(100_000, 64 * 1024);
+    assert!(large <= small, "peak grew from {} to {} bytes", small, large);
+}
+
+#[test]
+#[ignore = "hashes 5M leaves; run with --release -- --ignored"]
+fn test_external_builder_five_million_leaves() {
+    let small = check_external_build(10_000, 64 * 1024);
+    let large = check_external_build(5_000_000, 64 * 1024);
+    assert!(large <= small, "peak grew from {} to {} bytes", small, large);
+}
+
+#[test]
+fn test_external_builder_uses_private_directory() {
+    let parent = std::env::temp_dir().join(format!("merkle-parent-{}", std::process::id()));
+    std::fs::create_dir_all(&parent).unwrap();
+    let builder = ExternalMerkleBuilder::in_dir(&parent).unwrap();
+    let entries: Vec<_> = std::fs::read_dir(&parent).unwrap().map(|e| e.unwrap().path()).collect();
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].is_dir());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&entries[0]).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+
+    drop(builder);
+    assert_eq!(std::fs::read_dir(&parent).unwrap().count(), 0);
+    std::fs::remove_dir(&parent).unwrap();
+}
+
+#[test]
+fn test_build_allocates_each_level_once() {
+    let hasher = TreeHasher::default();
+    let leaves = |n: u8| (0..n).map(|i| Node::new_leaf(&[i], &hasher)).collect::<Vec<_>>();
+
+    // Two leaves cost one level vector plus whatever one parent needs
+    let two = leaves(2);
+    let (_, pair_allocations, _) = measure_allocations(|| build_root(two, &hasher));
+    let per_parent = pair_allocations - 1;
+
+    // Pre-sizing each level leaves exactly one vector allocation per level,
+    // where growing from `Vec::new()` would reallocate as it fills
+    for n in [3u8, 8, 100, 255] {
+        let nodes = leaves(n);
+        let levels = (n as usize).next_power_of_two().trailing_zeros() as usize;
+        let (_, allocations, _) = measure_allocations(|| build_root(nodes, &hasher));
+        assert_eq!(allocations, levels + (n as usize - 1) * per_parent, "{} leaves", n);
+    }
+}
+
+#[test]
+fn test_checked_split_limit() {
+    assert_eq!(checked_split(1), Some(0));
+    assert_eq!(checked_split(5), Some(4));
+    assert_eq!(checked_split(8), Some(4));
+    assert_eq!(checked_split(MAX_LEAVES), Some(MAX_LEAVES / 2));
+    assert_eq!(checked_split(MAX_LEAVES + 1), None);
+    assert_eq!(checked_split(usize::MAX), None);
+
+    // Leaf counts past the limit are turned away rather than overflowing
+    assert!(!is_possible_path(&[true], usize::MAX));
+    assert_eq!(leaf_index_for_path(&[true; 64], usize::MAX), None);
+    let mut partial = PartialMerkleTree::new(hash("a"), usize::MAX);
+    assert_eq!(
+        partial.add_subtree(0..1, hash("a")),
+        Err(MerkleError::TooManyLeaves { max: MAX_LEAVES })
+    );
+    assert!(partial.verified_ranges().is_empty());
+}
+
+#[test]
+fn test_absence_proof_with_hostile_index_does_not_overflow() {
+    let sorted = SortedMerkleTree::new(blocks(&["a", "c"])).unwrap();
+    let mut proof = sorted.prove_absence(b"d").unwrap();
+    proof.left.as_mut().unwrap().index = usize::MAX;
+    assert!(!SortedMerkleTree::verify_absence(&sorted.root_hash().unwrap(), 2, b"d", &proof));
+}
+
+#[test]
+fn test_frozen_tree_matches_mutable_tree() {
+    for n in [0, 1, 2, 5, 8, 11] {
+        let data: Vec<Vec<u8>> = (0..n).map(|i| vec![i as u8]).collect();
+        let tree = MerkleTree::new(data.clone());
+        let proofs: Vec<_> = (0..n).map(|i| tree.generate_proof(i)).collect();
+        let root = tree.root_hash();
+
+        let frozen = tree.freeze();
+        assert_eq!(frozen.root_hash(), root);
+        assert_eq!(frozen.leaf_count(), n);
+        for (i, block) in data.iter().enumerate() {
+            assert_eq!(frozen.generate_proof(i), proofs[i]);
+            let (proof, proof_path) = frozen.generate_proof(i).unwrap();
+            assert!(frozen.verify(block, &proof, &proof_path));
+            assert!(!frozen.verify(b"other", &proof, &proof_path));
+        }
+        assert_eq!(frozen.generate_proof(n), None);
+    }
+}
+
+#[test]
+fn test_verify_sha512_proof_standalone() {
+    let data = blocks(&["a", "b", "c", "d", "e"]);
+    let tree = MerkleTree::builder()
+        .leaf_hasher(HashAlgorithm::Sha512)
+        .node_hasher(HashAlgorithm::Sha512)
+        .build(data.clone());
+    let root = tree.root_hash().unwrap();
+    assert_eq!(root.len(), 64);
+
+    for (i, block) in data.iter().enumerate() {
+        let (proof, proof_path) = tree.generate_proof(i).unwrap();
+        assert!(verify_proof_with::<Sha512>(&root, block, &proof, &proof_path));
+        assert!(!verify_proof_with::<Sha256>(&root, block, &proof, &proof_path));
+        assert!(!verify_proof_with::<Sha512>(&root, b"x", &proof, &proof_path));
+    }
+
+    let sha256_tree = MerkleTree::new(data.clone());
+    let (proof, proof_path) = sha256_tree.generate_proof(1).unwrap();
+    assert!(verify_proof(&sha256_tree.root_hash().unwrap(), &data[1], &proof, &proof_path));
+}
+
+#[test]
+fn test_replace_all_rebuilds_in_place() {
+    let mut tree = MerkleTree::from_strs(&["a", "b", "c", "d"]);
+    let (old_proof, old_path) = tree.generate_proof(1).unwrap();
+
+    tree.replace_all(blocks(&["w", "x", "y"]));
+    assert_eq!(tree.root_hash(), MerkleTree::from_strs(&["w", "x", "y"]).root_hash());
+    assert_eq!(tree.leaf_count(), 3);
+    assert!(!tree.verify(b"b", &old_proof, &old_path));
+
+    let (proof, proof_path) = tree.generate_proof(1).unwrap();
+    assert!(tree.verify(b"x", &proof, &proof_path));
+}
+
+#[test]
+fn test_binary_round_trip() {
+    let data = blocks(&["a", "b", "c", "d", "e"]);
+    for tree in [
+        MerkleTree::new(data.clone()),
+        MerkleTree::builder()
+            .node_hasher(HashAlgorithm::Sha512)
+            .node_separator(vec![0xff, 0x00])
+            .build(data.clone()),
+        MerkleTree::new(Vec::new()),
+    ] {
+        let loaded = MerkleTree::from_bytes(&tree.to_bytes().unwrap()).unwrap();
+        assert_eq!(loaded.root_hash(), tree.root_hash());
+        assert_eq!(loaded.leaf_count(), tree.leaf_count());
+        if let Some((proof, proof_path)) = tree.generate_proof(3) {
+            assert!(loaded.verify(&data[3], &proof, &proof_path));
+        }
+    }
+
+    // The header has one byte for the separator's length
+    let long_separator = MerkleTree::builder().node_separator(vec![0; 256]).build(data);
+    assert_eq!(
+        long_separator.to_bytes().unwrap_err(),
+        MerkleError::BadFormat("node separator is over 255 bytes")
+    );
+    assert!(long_separator.to_bytes_with_nodes().is_err());
+
+    // Only one leaf hash length is recorded
+    let mixed = MerkleTree::from_hashes(vec![vec![1; 32], vec![2; 20]]);
+    assert_eq!(
+        mixed.to_bytes().unwrap_err(),
+        MerkleError::BadFormat("leaf hashes differ in length")
+    );
+    assert!(mixed.to_bytes_with_nodes().is_err());
+}
+
+#[test]
+fn test_from_bytes_rejects_bad_header() {
+    let bytes = MerkleTree::from_strs(&["a", "b"]).to_bytes().unwrap();
+
+    let mut corrupted = bytes.clone();
+    corrupted[0] ^= 0xff;
+    assert_eq!(
+        MerkleTree::from_bytes(&corrupted).unwrap_err(),
+        MerkleError::BadFormat("missing magic header")
+    );
+
+    let mut future = bytes.clone();
+    future[4] = 3;
+    assert_eq!(
+        MerkleTree::from_bytes(&future).unwrap_err(),
+        MerkleError::BadFormat("unsupported version")
+    );
+
+    assert!(matches!(
+        MerkleTree::from_bytes(&bytes[..bytes.len() - 1]),
+        Err(MerkleError::BadFormat(_))
+    ));
+    assert!(matches!(MerkleTree::from_bytes(b"MR"), Err(MerkleError::BadFormat(_))));
+}
+
+#[test]
+fn test_all_node_hashes() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d"]);
+    let hashes = tree.all_node_hashes();
+    assert_eq!(hashes.len(), 7);
+    assert_eq!(hashes.first(), tree.root_hash().as_ref());
+    for leaf in &tree {
+        assert!(hashes.iter().any(|hash| hash == leaf));
+    }
+
+    // Duplicate leaves and the subtrees above them are only listed once
+    let duplicated = MerkleTree::from_strs(&["a", "b", "a", "b"]);
+    assert_eq!(duplicated.all_node_hashes().len(), 4);
+    assert!(MerkleTree::new(Vec::new()).all_node_hashes().is_empty());
+}
+
+#[test]
+fn test_verify_against_any_candidate_root() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d"]);
+    let stale = MerkleTree::from_strs(&["a", "b", "c"]).root_hash().unwrap();
+    let other = MerkleTree::from_strs(&["x", "y"]).root_hash().unwrap();
+    let root = tree.root_hash().unwrap();
+
+    let (proof, proof_path) = tree.generate_proof(2).unwrap();
+    let candidates: [&[u8]; 3] = [&stale, &root, &other];
+    assert_eq!(verify_against_any(&candidates, b"c", &proof, &proof_path), Some(1));
+    assert_eq!(verify_against_any(&candidates, b"z", &proof, &proof_path), None);
+    assert_eq!(verify_against_any(&[], b"c", &proof, &proof_path), None);
+}
+
+#[test]
+fn test_merkle_writer_matches_file_chunks() {
+    let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+    let path = std::env::temp_dir().join(format!("merkle-writer-test-{}.bin", std::process::id()));
+    std::fs::write(&path, &data).unwrap();
+
+    let mut writer = MerkleWriter::new(1024);
+    std::io::copy(&mut &data[..], &mut writer).unwrap();
+    let tree = writer.finish();
+    let from_file = MerkleTree::from_file_chunks(&path, 1024).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(tree.leaf_count(), 10);
+    assert_eq!(tree.root_hash(), from_file.root_hash());
+    let chunks: Vec<Vec<u8>> = data.chunks(1024).map(|chunk| chunk.to_vec()).collect();
+    assert_eq!(tree.root_hash(), MerkleTree::new(chunks).root_hash());
+}
+
+#[cfg(feature = "memmap2")]
+#[test]
+fn test_new_leaf_mmap_matches_read_leaf() {
+    let data: Vec<u8> = (0..100_000u32).map(|i| (i % 253) as u8).collect();
+    let path = std::env::temp_dir().join(format!("merkle-mmap-test-{}.bin", std::process::id()));
+    std::fs::write(&path, &data).unwrap();
+    let mapped = Node::new_leaf_mmap(&path).unwrap();
+    let read = std::fs::read(&path).unwrap();
+    std::fs::write(&path, b"").unwrap();
+    let empty = Node::new_leaf_mmap(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(mapped.hash(), MerkleTree::new(vec![read]).root_hash().unwrap());
+    assert_eq!(empty.hash(), MerkleTree::new(vec![Vec::new()]).root_hash().unwrap());
+    assert!(Node::new_leaf_mmap(&path).is_err());
+}
+
+#[test]
+fn test_node_subtree_height_and_leaves() {
+    let hasher = TreeHasher::default();
+    let leaf = |data: &[u8]| Node::new_leaf(data, &hasher);
+
+    // (a, b) paired with c promoted alongside them
+    let ab = Node::new_parent(leaf(b"a"), leaf(b"b"), &hasher);
+    let root = Node::new_parent(ab, leaf(b"c"), &hasher);
+    assert_eq!(root.subtree_height(), 2);
+    assert_eq!(root.subtree_leaves(), 3);
+
+    let (left, right) = root.children().unwrap();
+    assert_eq!((left.subtree_height(), left.subtree_leaves()), (1, 2));
+    assert_eq!((right.subtree_height(), right.subtree_leaves()), (0, 1));
+    assert!(right.children().is_none());
+
+    let tree = MerkleTree::from_strs(&["a", "b", "c"]);
+    assert_eq!(tree.root_node().map(|node| node.hash()), Some(root.hash()));
+    assert_eq!(tree.root_node().map(Node::subtree_leaves), Some(3));
+}
+
+#[test]
+fn test_verify_detailed_outcomes() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d"]);
+    let (proof, proof_path) = tree.generate_proof(2).unwrap();
+    let root = tree.root_hash().unwrap();
+
+    assert_eq!(tree.verify_detailed(b"c", &proof, &proof_path), VerifyOutcome::Valid);
+    assert_eq!(
+        tree.verify_detailed(b"c", &proof, &proof_path[..1]),
+        VerifyOutcome::LengthMismatch { proof_len: 2, path_len: 1 }
+    );
+    assert_eq!(
+        tree.verify_detailed(b"c", &vec![vec![0; 32]; 65], &[false; 65]),
+        VerifyOutcome::ProofTooLong { len: 65, max: DEFAULT_MAX_PROOF_LEN }
+    );
+    match tree.verify_detailed(b"x", &proof, &proof_path) {
+        VerifyOutcome::RootMismatch { computed, expected } => {
+            assert_eq!(expected, root);
+            assert_ne!(computed, root);
+        }
+        outcome => panic!("unexpected outcome {:?}", outcome),
+    }
+    assert_eq!(
+        MerkleTree::new(Vec::new()).verify_detailed(b"c", &proof, &proof_path),
+        VerifyOutcome::EmptyTree
+    );
+}
+
+#[test]
+fn test_append_subtree_matches_pushing_leaves() {
+    let mut tree = MerkleTree::from_strs(&["a", "b", "c", "d"]);
+    tree.append_subtree(MerkleTree::from_strs(&["e", "f", "g", "h"]));
+    let eight = MerkleTree::from_strs(&["a", "b", "c", "d", "e", "f", "g", "h"]);
+    assert_eq!(tree.root_hash(), eight.root_hash());
+    assert_eq!(tree.leaf_count(), 8);
+    let (proof, proof_path) = tree.generate_proof(5).unwrap();
+    assert!(tree.verify(b"f", &proof, &proof_path));
+
+    // Unaligned sizes fall back to rebuilding from the combined leaves
+    let mut tree = MerkleTree::from_strs(&["a", "b", "c"]);
+    tree.append_subtree(MerkleTree::from_strs(&["d", "e"]));
+    assert_eq!(tree.root_hash(), MerkleTree::from_strs(&["a", "b", "c", "d", "e"]).root_hash());
+
+    let mut empty = MerkleTree::new(Vec::new());
+    empty.append_subtree(MerkleTree::from_strs(&["a"]));
+    assert_eq!(empty.root_hash(), MerkleTree::from_strs(&["a"]).root_hash());
+}
+
+#[test]
+fn test_node_hex_and_base64_encodings() {
+    let tree = MerkleTree::from_strs(&["a"]);
+    let root = tree.root_node().unwrap();
+    let lower = "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb";
+    assert_eq!(format!("{}", root), lower);
+    assert_eq!(format!("{:x}", root), lower);
+    assert_eq!(format!("{:X}", root), lower.to_uppercase());
+    assert_eq!(root.to_base64(), "ypeBEsobvcr6wjGzmiPcTaeG7/gUfE5yuYB3ha/uSLs=");
+}
+
+#[test]
+fn test_sibling_indices_show_promotions() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]);
+    assert_eq!(tree.sibling_indices(0), vec![Some(1), Some(1), Some(1)]);
+    assert_eq!(tree.sibling_indices(3), vec![Some(2), Some(0), Some(1)]);
+    assert_eq!(tree.sibling_indices(4), vec![None, None, Some(0)]);
+    assert!(tree.sibling_indices(5).is_empty());
+
+    for index in 0..5 {
+        let paired = tree.sibling_indices(index).iter().flatten().count();
+        assert_eq!(paired, tree.generate_proof(index).unwrap().0.len());
+    }
+}
+
+#[test]
+fn test_is_perfect() {
+    assert!(MerkleTree::from_strs(&["a", "b", "c", "d"]).is_perfect());
+    assert!(!MerkleTree::from_strs(&["a", "b", "c", "d", "e"]).is_perfect());
+    assert!(MerkleTree::from_strs(&["a"]).is_perfect());
+    assert!(!MerkleTree::new(Vec::new()).is_perfect());
+}
+
+#[test]
+fn test_subtree_root_of_aligned_range() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d", "e", "f", "g", "h"]);
+    let shard = MerkleTree::from_strs(&["a", "b", "c", "d"]);
+    assert_eq!(tree.subtree_root(0, 4), shard.root_hash());
+    assert_eq!(tree.subtree_root(4, 8), MerkleTree::from_strs(&["e", "f", "g", "h"]).root_hash());
+    assert_eq!(tree.subtree_root(6, 7), Some(hash("g")));
+    assert_eq!(tree.subtree_root(0, 8), tree.root_hash());
+
+    for (start, end) in [(1, 3), (2, 6), (0, 3), (4, 4), (8, 9), (0, 9)] {
+        assert_eq!(tree.subtree_root(start, end), None, "{}..{}", start, end);
+    }
+
+    let uneven = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]);
+    assert_eq!(uneven.subtree_root(0, 4), shard.root_hash());
+    assert_eq!(uneven.subtree_root(4, 5), Some(hash("e")));
+}
+
+#[test]
+fn test_subtree_extracts_left_half() {
+    let data = blocks(&["a", "b", "c", "d", "e", "f", "g", "h"]);
+    let tree = MerkleTree::builder().retain_leaf_data().build(data.clone());
+    let half = tree.subtree(&[Side::Left]).unwrap();
+    assert_eq!(half.leaf_count(), 4);
+    assert_eq!(half.root_hash(), MerkleTree::new(data[..4].to_vec()).root_hash());
+    assert_eq!(half.leaf_data(3), Some(&b"d"[..]));
+
+    let half_root = half.root_hash().unwrap();
+    let (proof, proof_path) = half.generate_proof(2).unwrap();
+    assert!(verify_proof(&half_root, b"c", &proof, &proof_path));
+    let (full_proof, _) = tree.generate_proof(2).unwrap();
+    assert_eq!(proof[..], full_proof[..2]);
+
+    let leaf = tree.subtree(&[Side::Right, Side::Right, Side::Left]).unwrap();
+    assert_eq!(leaf.root_hash(), Some(hash("g")));
+    assert_eq!(leaf.leaf_data(0), Some(&b"g"[..]));
+    assert!(tree.subtree(&[Side::Left; 4]).is_none());
+    assert_eq!(tree.subtree(&[]).unwrap().root_hash(), tree.root_hash());
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn test_tracing_spans_fire() {
+    use std::sync::{Arc, Mutex};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    /// Records the name of every span opened
+    struct SpanNames(Arc<Mutex<Vec<&'static str>>>);
+
+    impl Subscriber for SpanNames {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut names = self.0.lock().unwrap();
+            names.push(span.metadata().name());
+            Id::from_u64(names.len() as u64)
+        }
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    let names = Arc::new(Mutex::new(Vec::new()));
+    tracing::subscriber::with_default(SpanNames(names.clone()), || {
+        let tree = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]);
+        tree.generate_proof(2).unwrap();
+    });
+
+    let names = names.lock().unwrap();
+    assert_eq!(names.iter().filter(|&&name| name == "build_level").count(), 3);
+    assert_eq!(names.iter().filter(|&&name| name == "generate_proof").count(), 1);
+}
+
+#[test]
+fn test_verify_constant_time() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]);
+    for (index, data) in ["a", "b", "c", "d", "e"].iter().enumerate() {
+        let (proof, proof_path) = tree.generate_proof(index).unwrap();
+        assert!(tree.verify_constant_time(data.as_bytes(), &proof, &proof_path));
+        assert!(!tree.verify_constant_time(b"x", &proof, &proof_path));
+
+        let mut tampered = proof.clone();
+        tampered[0][31] ^= 1;
+        assert!(!tree.verify_constant_time(data.as_bytes(), &tampered, &proof_path));
+        assert!(!tree.verify_constant_time(data.as_bytes(), &proof, &proof_path[1..]));
+    }
+
+    let (proof, proof_path) = tree.generate_proof(0).unwrap();
+    let flipped: Vec<bool> = proof_path.iter().map(|is_right| !is_right).collect();
+    assert!(!tree.verify_constant_time(b"a", &proof, &flipped));
+    assert!(!MerkleTree::new(Vec::new()).verify_constant_time(b"a", &[], &[]));
+    assert!(MerkleTree::from_strs(&["a"]).verify_constant_time(b"a", &[], &[]));
+
+    // An oversized proof is turned away before a single hash is computed
+    let oversized = vec![hash("a"); 100_000];
+    let path = vec![false; oversized.len()];
+    let (accepted, allocations, _) =
+        measure_allocations(|| tree.verify_constant_time(b"a", &oversized, &path));
+    assert!(!accepted);
+    assert_eq!(allocations, 0);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_from_parallel_iterator_matches_sequential() {
+    use rayon::prelude::*;
+
+    let data: Vec<Vec<u8>> = (0..1000).map(|i| format!("leaf {}", i).into_bytes()).collect();
+    let sequential = MerkleTree::new(data.clone());
+    let parallel = MerkleTree::from_parallel_iterator(data.par_iter());
+    assert_eq!(parallel.root_hash(), sequential.root_hash());
+    assert_eq!(parallel.leaf_count(), 1000);
+
+    let filtered = MerkleTree::from_parallel_iterator(data.into_par_iter().filter(|d| d.len() > 6));
+    assert_eq!(filtered.leaf_count(), 990);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_parallel_and_sequential_roots_agree_for_every_shape() {
+    use rayon::prelude::*;
+
+    for leaf_count in 0..=33 {
+        let data: Vec<Vec<u8>> = (0..leaf_count).map(|i| vec![i as u8; i % 5]).collect();
+        let sequential = MerkleTree::new(data.clone());
+        let parallel = MerkleTree::from_parallel_iterator(data.par_iter());
+        assert_eq!(parallel.root_hash(), sequential.root_hash(), "{} leaves", leaf_count);
+        for (index, block) in data.iter().enumerate() {
+            assert_eq!(parallel.generate_proof(index), sequential.generate_proof(index));
+            let (proof, proof_path) = parallel.generate_proof(index).unwrap();
+            assert!(sequential.verify(block, &proof, &proof_path));
+        }
+    }
+}
+
+#[test]
+fn test_leaf_transform_length_prefix() {
+    fn length_prefixed(data: &[u8]) -> Vec<u8> {
+        [&(data.len() as u32).to_be_bytes()[..], data].concat()
+    }
+
+    let data = blocks(&["a", "bc", "def"]);
+    let tree = MerkleTree::new_with_leaf_transform(data.clone(), length_prefixed);
+    assert_ne!(tree.root_hash(), MerkleTree::new(data.clone()).root_hash());
+    let prefixed: Vec<Vec<u8>> = data.iter().map(|d| length_prefixed(d)).collect();
+    assert_eq!(tree.root_hash(), MerkleTree::new(prefixed).root_hash());
+
+    for (index, block) in data.iter().enumerate() {
+        let (proof, proof_path) = tree.generate_proof(index).unwrap();
+        assert!(tree.verify_with_leaf_transform(block, &proof, &proof_path, length_prefixed));
+        assert!(!tree.verify(block, &proof, &proof_path));
+    }
+}
+
+#[test]
+fn test_indexed_transform_prefixes_even_leaves() {
+    let transform = |index: usize, data: &[u8]| {
+        if index.is_multiple_of(2) {
+            [&(data.len() as u32).to_be_bytes()[..], data].concat()
+        } else {
+            data.to_vec()
+        }
+    };
+
+    let data = blocks(&["a", "bc", "def", "g", "hi"]);
+    let tree = MerkleTree::new_with_indexed_transform(data.clone(), &transform);
+    let encoded: Vec<Vec<u8>> = data.iter()
+        .enumerate()
+        .map(|(index, d)| transform(index, d))
+        .collect();
+    assert_eq!(tree.root_hash(), MerkleTree::new(encoded).root_hash());
+    assert_ne!(tree.root_hash(), MerkleTree::new(data.clone()).root_hash());
+
+    for (index, block) in data.iter().enumerate() {
+        let (proof, proof_path) = tree.generate_proof(index).unwrap();
+        assert!(tree.verify_with_indexed_transform(index, block, &proof, &proof_path, &transform));
+        assert_eq!(tree.verify(block, &proof, &proof_path), !index.is_multiple_of(2));
+    }
+    let (proof, proof_path) = tree.generate_proof(1).unwrap();
+    assert!(!tree.verify_with_indexed_transform(0, b"bc", &proof, &proof_path, &transform));
+}
+
+#[test]
+fn test_verify_rejects_impossible_path() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]);
+    let (proof, proof_path) = tree.generate_proof(2).unwrap();
+    assert_eq!(proof_path, vec![false, true, false]);
+
+    // Turning left at the root leads under the first four leaves, which
+    // takes two more levels to reach a leaf, not one
+    let (short, impossible) = (&proof[1..], &proof_path[1..]);
+    assert_eq!(impossible, [true, false]);
+    assert_eq!(
+        tree.try_verify(b"c", short, impossible),
+        Err(MerkleError::ImpossiblePath { leaf_count: 5 })
+    );
+    assert!(!tree.verify_leaf_hash(&hash("c"), short, impossible));
+    assert_eq!(tree.verify_detailed(b"c", short, impossible), VerifyOutcome::ImpossiblePath);
+
+    let (short_proof, short_path) = tree.generate_proof(4).unwrap();
+    assert_eq!(
+        tree.try_verify(b"e", &short_proof[..0], &short_path[..0]),
+        Err(MerkleError::ImpossiblePath { leaf_count: 5 })
+    );
+    assert_eq!(tree.try_verify(b"c", &proof, &proof_path), Ok(true));
+
+    // Every verifier applies the same structural checks
+    let frozen = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]).freeze();
+    let mut truncated = proof.clone();
+    truncated[0].truncate(16);
+    assert!(frozen.verify(b"c", &proof, &proof_path));
+    assert!(!tree.verify_constant_time(b"c", short, impossible));
+    for verifier in [&tree as &dyn MerkleStructure, &frozen] {
+        assert!(!verifier.verify(b"c", short, impossible));
+        assert!(!verifier.verify(b"c", &truncated, &proof_path));
+    }
+
+    // A path whose top is a path of the tree may be a composed proof, its
+    // bottom climbing through a sub-tree, and is left to the hashing
+    let composed = [false, true, true];
+    assert_eq!(tree.try_verify(b"c", &proof, &composed), Ok(false));
+}
+
+#[test]
+fn test_snapshot_and_restore() {
+    use std::sync::{Arc, Mutex};
+
+    let mut tree = MerkleTree::with_metadata(vec![(b"a".to_vec(), 1), (b"b".to_vec(), 2)]);
+    let snapshot = tree.snapshot();
+    let root = tree.root_hash().unwrap();
+    assert_eq!(snapshot.root_hash(), Some(root.as_slice()));
+
+    tree.push_with_metadata(b"c", 3);
+    tree.update_leaf(0, b"z").unwrap();
+    tree.delete_leaf(1).unwrap();
+    assert_ne!(tree.root_hash().unwrap(), root);
+
+    let notified = Arc::new(Mutex::new(Vec::new()));
+    let seen = Arc::clone(&notified);
+    tree.on_root_change(move |root| seen.lock().unwrap().push(root.to_vec()));
+    tree.restore(snapshot);
+    assert_eq!(tree.root_hash(), Some(root.clone()));
+    assert_eq!(tree.root_hex(), Some(hex::encode(&root).as_str()));
+    assert_eq!(tree.leaf_count(), 2);
+    assert_eq!(tree.metadata(1), Some(&2));
+    assert_eq!(*notified.lock().unwrap(), vec![root]);
+    assert!(tree.verify_internal(1));
+}
+
+#[test]
+fn test_length_prefixed_leaves() {
+    let prefixed = || MerkleTree::builder().length_prefixed_leaves();
+    let ab_c = prefixed().build(blocks(&["ab", "c"]));
+    let a_bc = prefixed().build(blocks(&["a", "bc"]));
+    assert_ne!(ab_c.root_hash(), a_bc.root_hash());
+
+    let leaf: Vec<u8> = Sha256::digest([&2u64.to_le_bytes()[..], b"ab"].concat()).to_vec();
+    assert_eq!(ab_c.leaves().next(), Some(leaf.as_slice()));
+    assert_eq!(ab_c.root_hash(), Some(hash_pair(&leaf, ab_c.leaves().nth(1).unwrap())));
+    assert_ne!(ab_c.root_hash(), MerkleTree::from_strs(&["ab", "c"]).root_hash());
+
+    let (proof, proof_path) = ab_c.generate_proof(1).unwrap();
+    assert!(ab_c.verify(b"c", &proof, &proof_path));
+    assert!(!MerkleTree::from_strs(&["ab", "c"]).verify(b"c", &proof, &proof_path));
+
+    let mut loaded = MerkleTree::from_bytes(&ab_c.to_bytes().unwrap()).unwrap();
+    assert_eq!(loaded.root_hash(), ab_c.root_hash());
+    loaded.push(b"d");
+    assert_eq!(loaded.root_hash(), prefixed().build(blocks(&["ab", "c", "d"])).root_hash());
+}
+
+#[test]
+fn test_verify_rejects_truncated_sibling() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d"]);
+    let (mut proof, proof_path) = tree.generate_proof(0).unwrap();
+    proof[1].truncate(16);
+    assert_eq!(
+        tree.try_verify(b"a", &proof, &proof_path),
+        Err(MerkleError::InvalidSiblingLength { index: 1, len: 16, expected: 32 })
+    );
+    assert!(!tree.verify(b"a", &proof, &proof_path));
+    assert!(!tree.verify_leaf_hash(&hash("a"), &proof, &proof_path));
+    assert_eq!(
+        tree.verify_detailed(b"a", &proof, &proof_path),
+        VerifyOutcome::InvalidSiblingLength { index: 1, len: 16, expected: 32 }
+    );
+
+    // Leaf hashes given to `from_hashes` set the expected leaf length
+    let short_hashes: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 20]).collect();
+    let short = MerkleTree::from_hashes(short_hashes.clone());
+    for (index, leaf_hash) in short_hashes.iter().enumerate() {
+        let (proof, proof_path) = short.generate_proof(index).unwrap();
+        assert!(short.verify_leaf_hash(leaf_hash, &proof, &proof_path));
+    }
+
+    // Leaf and node hashes of different lengths are both accepted
+    let mixed = MerkleTree::builder()
+        .node_hasher(HashAlgorithm::Sha512)
+        .build(blocks(&["a", "b", "c"]));
+    let (proof, proof_path) = mixed.generate_proof(2).unwrap();
+    assert_eq!(proof[0].len(), 64);
+    assert_eq!(mixed.try_verify(b"c", &proof, &proof_path), Ok(true));
+    let (proof, proof_path) = mixed.generate_proof(0).unwrap();
+    assert_eq!(proof[0].len(), 32);
+    assert_eq!(mixed.try_verify(b"a", &proof, &proof_path), Ok(true));
+}
+
+#[cfg(feature = "futures")]
+#[test]
+fn test_from_stream_matches_new() {
+    let data = blocks(&["a", "b", "c", "d", "e"]);
+    let stream = futures::stream::iter(data.clone());
+    let tree = futures::executor::block_on(MerkleTree::from_stream(stream));
+    assert_eq!(tree.root_hash(), MerkleTree::new(data).root_hash());
+    assert_eq!(tree.leaf_count(), 5);
+
+    let empty = futures::executor::block_on(MerkleTree::from_stream(futures::stream::empty()));
+    assert_eq!(empty.root_hash(), None);
+}
+
+#[test]
+fn test_verify_counted() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]);
+    let (proof, proof_path) = tree.generate_proof(1).unwrap();
+    assert_eq!(tree.verify_counted(b"b", &proof, &proof_path), (true, proof.len() + 1));
+    assert_eq!(tree.verify_counted(b"x", &proof, &proof_path), (false, 4));
+
+    let (proof, proof_path) = tree.generate_proof(4).unwrap();
+    assert_eq!(tree.verify_counted(b"e", &proof, &proof_path), (true, 2));
+    assert_eq!(tree.verify_counted(b"e", &proof, &[]), (false, 0));
+}
+
+#[test]
+fn test_commit_tree_hides_values_and_opens() {
+    let values = blocks(&["alice", "bob", "carol"]);
+    let (tree, salts) = CommitMerkleTree::commit(values.clone());
+    let (again, _) = CommitMerkleTree::commit(values.clone());
+    let plain = MerkleTree::new(values.clone());
+    assert_eq!(salts.len(), 3);
+    assert_ne!(tree.root_hash(), again.root_hash());
+    assert_ne!(tree.root_hash(), plain.root_hash());
+
+    let root = tree.root_hash().unwrap();
+    for (index, value) in values.iter().enumerate() {
+        let (opened, salt, proof) = tree.open(index).unwrap();
+        assert_eq!(opened, value.as_slice());
+        assert_eq!(salt, salts[index]);
+        assert!(CommitMerkleTree::verify_opening(&root, opened, &salt, &proof));
+        assert!(!CommitMerkleTree::verify_opening(&root, b"mallory", &salt, &proof));
+        assert!(!CommitMerkleTree::verify_opening(&root, opened, &[0; SALT_LEN], &proof));
+    }
+    assert!(tree.open(3).is_none());
+
+    let rebuilt = CommitMerkleTree::with_salts(values, salts);
+    assert_eq!(rebuilt.root_hash(), tree.root_hash());
+}
+
+#[test]
+fn test_verify_with_index_disambiguates_duplicates() {
+    let tree = MerkleTree::from_strs(&["a", "dup", "b", "dup", "c"]);
+    let (proof, proof_path) = tree.generate_proof(1).unwrap();
+
+    assert!(tree.verify(b"dup", &proof, &proof_path));
+    assert!(tree.verify_with_index(1, b"dup", &proof, &proof_path));
+    assert!(!tree.verify_with_index(3, b"dup", &proof, &proof_path));
+
+    let (proof, proof_path) = tree.generate_proof(3).unwrap();
+    assert!(tree.verify_with_index(3, b"dup", &proof, &proof_path));
+    assert!(!tree.verify_with_index(1, b"dup", &proof, &proof_path));
+    assert!(!tree.verify_with_index(3, b"a", &proof, &proof_path));
+}
+
+#[test]
+fn test_verify_with_either_path_convention() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]);
+    let (proof, proof_path) = tree.generate_proof(2).unwrap();
+    let inverted: Vec<bool> = proof_path.iter().map(|is_right| !is_right).collect();
+
+    let (current, sibling) = (PathConvention::CurrentIsRight, PathConvention::SiblingIsRight);
+
+    assert!(tree.verify_with_convention(b"c", &proof, &proof_path, current));
+    assert!(tree.verify_with_convention(b"c", &proof, &inverted, sibling));
+    assert!(!tree.verify_with_convention(b"c", &proof, &proof_path, sibling));
+    assert!(!tree.verify_with_convention(b"c", &proof, &inverted, current));
+    assert_eq!(PathConvention::default(), PathConvention::CurrentIsRight);
+}
+
+#[test]
+fn test_subtree_inclusion_proof() {
+    let data: Vec<Vec<u8>> = (0..16).map(|i| format!("leaf {}", i).into_bytes()).collect();
+    let tree = MerkleTree::new(data.clone());
+
+    let (shard_root, proof) = tree.subtree_inclusion_proof(4, 8).unwrap();
+    assert_eq!(Some(shard_root.clone()), tree.subtree_root(4, 8));
+    assert_eq!(proof.siblings.len(), 2);
+    assert_eq!(proof.path, vec![Side::Right, Side::Left]);
+    assert!(tree.verify_subtree_inclusion(&shard_root, &proof));
+    assert!(!tree.verify_subtree_inclusion(&hash("x"), &proof));
+
+    // The shard's leaves check out against its own root
+    let shard = MerkleTree::new(data[4..8].to_vec());
+    assert_eq!(shard.root_hash(), Some(shard_root));
+
+    assert!(tree.subtree_inclusion_proof(3, 7).is_none());
+    let (root, proof) = tree.subtree_inclusion_proof(0, 16).unwrap();
+    assert_eq!((Some(root), proof.siblings.len()), (tree.root_hash(), 0));
+
+    let odd = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]);
+    let (tail, proof) = odd.subtree_inclusion_proof(4, 5).unwrap();
+    assert_eq!(tail, hash("e"));
+    assert!(odd.verify_subtree_inclusion(&tail, &proof));
+}
+
+#[test]
+fn test_clear_empties_tree_for_reuse() {
+    let mut tree = MerkleTreeBuilder::new()
+        .retain_leaf_data()
+        .build(blocks(&["a", "b", "c"]));
+    tree.clear();
+    assert_eq!(tree.leaf_count(), 0);
+    assert_eq!(tree.root_hash(), None);
+    assert_eq!(tree.leaf_data(0), None);
+
+    tree.push(b"x");
+    tree.push(b"y");
+    assert_eq!(tree.root_hash(), MerkleTree::from_strs(&["x", "y"]).root_hash());
+    assert_eq!(tree.leaf_data(1), Some(&b"y"[..]));
+
+    let mut pooled = MerkleTree::<()>::default();
+    assert_eq!(pooled.root_hash(), None);
+    pooled.push(b"x");
+    assert_eq!(pooled.root_hash(), Some(hash("x")));
+}
+
+#[test]
+fn test_to_debug_json() {
+    let tree = MerkleTree::from_strs(&["a", "b"]);
+    let leaf = |data| format!(r#"{{"hash":"{}","children":[]}}"#, hex::encode(hash(data)));
+    let expected = format!(
+        r#"{{"leaf_count":2,"root":{{"hash":"{}","children":[{},{}]}}}}"#,
+        hex::encode(hash_pair(&hash("a"), &hash("b"))),
+        leaf("a"),
+        leaf("b")
+    );
+    assert_eq!(tree.to_debug_json(), expected);
+    assert_eq!(MerkleTree::new(Vec::new()).to_debug_json(), r#"{"leaf_count":0,"root":null}"#);
+}
+
+#[test]
+fn test_common_ancestor_level() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d", "e", "f", "g", "h"]);
+    assert_eq!(tree.common_ancestor_level(0, 1), Some(1));
+    assert_eq!(tree.common_ancestor_level(1, 2), Some(2));
+    assert_eq!(tree.common_ancestor_level(0, 4), Some(3));
+    assert_eq!(tree.common_ancestor_level(7, 0), Some(3));
+    assert_eq!(tree.common_ancestor_level(5, 5), Some(0));
+    assert_eq!(tree.common_ancestor_level(0, 8), None);
+}
+
+#[test]
+fn test_padded_proofs_have_fixed_depth() {
+    let data = ["a", "b", "c", "d", "e"];
+    let tree = MerkleTree::from_strs(&data);
+    let root = tree.root_hash().unwrap();
+    for (index, block) in data.iter().enumerate() {
+        let proof = tree.generate_padded_proof(index, 8).unwrap();
+        assert_eq!(proof.siblings.len(), 8);
+        assert_eq!(proof.path.len(), 8);
+        assert!(verify_padded_proof(&root, block.as_bytes(), &proof, 8));
+        assert!(!verify_padded_proof(&root, block.as_bytes(), &proof, 9));
+        assert!(!verify_padded_proof(&root, b"x", &proof, 8));
+    }
+
+    // The last leaf is promoted twice, so most of its proof is padding
+    let proof = tree.generate_padded_proof(4, 3).unwrap();
+    assert_eq!(proof.siblings[1..], [PADDING_SIBLING.to_vec(), PADDING_SIBLING.to_vec()]);
+    assert!(tree.generate_padded_proof(0, 2).is_none());
+    assert!(tree.generate_padded_proof(5, 8).is_none());
+}
+
+#[test]
+fn test_max_leaf_size_rejects_oversized_leaf() {
+    let data = blocks(&["ab", "abc", "abcd", "abcde"]);
+    assert_eq!(
+        MerkleTree::builder().max_leaf_size(3).try_build(data.clone()).err(),
+        Some(MerkleError::LeafTooLarge { index: 2, size: 4 })
+    );
+
+    let tree = MerkleTree::builder().max_leaf_size(5).try_build(data.clone()).unwrap();
+    assert_eq!(tree.root_hash(), MerkleTree::new(data.clone()).root_hash());
+    assert!(MerkleTree::builder().try_build(data).is_ok());
+}
+
+#[test]
+#[should_panic(expected = "leaf 1 is 3 bytes")]
+fn test_build_panics_on_oversized_leaf() {
+    MerkleTree::builder().max_leaf_size(2).build(blocks(&["ab", "abc"]));
+}
+
+#[test]
+fn test_verify_internal_for_every_leaf() {
+    for size in [1, 2, 3, 5, 7, 8, 13, 16, 33] {
+        let data: Vec<Vec<u8>> = (0..size).map(|i| format!("leaf {}", i).into_bytes()).collect();
+        let tree = MerkleTree::new(data.clone());
+        assert!((0..size).all(|index| tree.verify_internal(index)), "size {}", size);
+        assert!(!tree.verify_internal(size));
+
+        let retained = MerkleTree::builder().retain_leaf_data().build(data);
+        assert!((0..size).all(|index| retained.verify_internal(index)), "size {}", size);
+    }
+    assert!(!MerkleTree::new(Vec::new()).verify_internal(0));
+}
+
+#[test]
+fn test_root_queries_between_pushes() {
+    let mut tree = MerkleTree::new(Vec::new());
+    let mut data = Vec::new();
+    for i in 0..10 {
+        let block = format!("block {}", i).into_bytes();
+        tree.push(&block);
+        data.push(block);
+
+        let expected = MerkleTree::new(data.clone()).root_hash().unwrap();
+        assert_eq!(tree.root_hash().as_ref(), Some(&expected));
+        assert_eq!(tree.root(), Some(expected.as_slice()));
+        assert_eq!(tree.root_hex(), Some(hex::encode(&expected).as_str()));
+        assert_eq!(tree.root_hex(), Some(hex::encode(&expected).as_str()));
+    }
+}
+
+#[test]
+fn test_root_queries_interleaved_with_mutations() {
+    fn check(tree: &MerkleTree, data: &[Vec<u8>]) {
+        let expected = MerkleTree::new(data.to_vec()).root_hash();
+        assert_eq!(tree.root_hash(), expected);
+        assert_eq!(tree.root_hex(), expected.as_ref().map(hex::encode).as_deref());
+        assert_eq!(tree.leaf_count(), data.len());
+    }
+
+    let mut data = blocks(&["a", "b"]);
+    let mut tree = MerkleTree::new(data.clone());
+    check(&tree, &data);
+
+    tree.push(b"c");
+    data.push(b"c".to_vec());
+    check(&tree, &data);
+
+    tree.update_leaf(1, b"x").unwrap();
+    data[1] = b"x".to_vec();
+    check(&tree, &data);
+
+    tree.insert_leaf(0, b"w").unwrap();
+    data.insert(0, b"w".to_vec());
+    check(&tree, &data);
+
+    tree.delete_leaf(2).unwrap();
+    data.remove(2);
+    check(&tree, &data);
+
+    tree.push(b"d");
+    data.push(b"d".to_vec());
+    tree.append_subtree(MerkleTree::from_strs(&["e", "f", "g", "h"]));
+    data.extend(blocks(&["e", "f", "g", "h"]));
+    check(&tree, &data);
+
+    tree.append_subtree(MerkleTree::from_strs(&["i"]));
+    data.push(b"i".to_vec());
+    check(&tree, &data);
+
+    tree.replace_all(blocks(&["y"]));
+    data = blocks(&["y"]);
+    check(&tree, &data);
+
+    tree.delete_leaf(0).unwrap();
+    data.clear();
+    check(&tree, &data);
+}
+
+#[test]
+fn test_cached_root_hex_follows_mutations() {
+    let mut tree = MerkleTree::from_strs(&["a", "b", "c"]);
+    let before = tree.root_hex().unwrap().to_owned();
+    // The cached string is handed out, not a fresh copy
+    assert!(std::ptr::eq(tree.root_hex().unwrap(), tree.root_hex().unwrap()));
+
+    tree.push(b"d");
+    let after_push = tree.root_hex().unwrap().to_owned();
+    assert_ne!(after_push, before);
+    assert_eq!(after_push, hex::encode(tree.root_hash().unwrap()));
+
+    tree.update_leaf(0, b"z").unwrap();
+    let after_update = tree.root_hex().unwrap().to_owned();
+    assert_ne!(after_update, after_push);
+    assert_eq!(after_update, hex::encode(tree.root_hash().unwrap()));
+
+    tree.replace_all(Vec::new());
+    assert_eq!(tree.root_hex(), None);
+}
+
+#[test]
+fn test_new_unchecked_matches_checked_construction() {
+    let leaf_hashes: Vec<Vec<u8>> = ["a", "b", "c"].iter().map(|data| hash(data)).collect();
+    let unchecked = MerkleTree::new_unchecked(leaf_hashes.clone());
+    let checked = MerkleTree::try_from_hashes(leaf_hashes).unwrap();
+    assert_eq!(unchecked.root_hash(), checked.root_hash());
+    assert_eq!(unchecked.root_hash(), MerkleTree::from_strs(&["a", "b", "c"]).root_hash());
+
+    let short = vec![hash("a"), vec![0u8; 20]];
+    assert_eq!(
+        MerkleTree::try_from_hashes(short).unwrap_err(),
+        MerkleError::InvalidLeafHashLength { index: 1, len: 20, expected: HASH_LEN }
+    );
+}
+
+fn print_root<T: MerkleStructure>(tree: &T) -> String {
+    tree.root().map(hex::encode).unwrap_or_default()
+}
+
+fn prove_and_verify<T: MerkleStructure>(tree: &T, index: usize, data: &[u8]) -> bool {
+    let (proof, proof_path) = tree.prove(index).unwrap();
+    tree.verify(data, &proof, &proof_path)
+}
+
+#[test]
+fn test_merkle_structure_is_generic_over_trees() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c"]);
+    let expected = tree.root_hex().unwrap().to_owned();
+    assert_eq!(print_root(&tree), expected);
+    assert!(prove_and_verify(&tree, 2, b"c"));
+    assert!(!prove_and_verify(&tree, 2, b"x"));
+
+    let frozen = tree.freeze();
+    assert_eq!(print_root(&frozen), expected);
+    assert!(prove_and_verify(&frozen, 1, b"b"));
+
+    assert_eq!(print_root(&MerkleTree::new(Vec::new())), "");
+}
+
+#[test]
+fn test_compact_releases_excess_capacity() {
+    // A burst of pushes followed by deletes leaves the per-leaf buffers
+    // sized for the peak
+    let mut tree = MerkleTreeBuilder::new().retain_leaf_data().build(Vec::new());
+    for i in 0..100u8 {
+        tree.push(&[i]);
+    }
+    for _ in 0..90 {
+        tree.delete_leaf(0).unwrap();
+    }
+    let root_before = tree.root_hash();
+    assert!(tree.leaf_data.as_ref().unwrap().capacity() >= 100);
+
+    tree.compact();
+    assert_eq!(tree.leaf_data.as_ref().unwrap().capacity(), 10);
+    assert_eq!(tree.root_hash(), root_before);
+    assert_eq!(tree.leaf_data(0), Some(&[90][..]));
+
+    let mut with_metadata = MerkleTree::with_metadata(Vec::new());
+    for i in 0..100u32 {
+        with_metadata.push_with_metadata(&i.to_le_bytes(), i);
+    }
+    for _ in 0..90 {
+        with_metadata.delete_leaf(0).unwrap();
+    }
+    assert!(with_metadata.metadata.capacity() >= 100);
+    with_metadata.compact();
+    assert_eq!(with_metadata.metadata.capacity(), 10);
+    assert_eq!(with_metadata.metadata(0), Some(&90));
+
+    let mut leaf_hash = Vec::with_capacity(128);
+    leaf_hash.extend(hash("a"));
+    let mut single = MerkleTree::from_hashes(vec![leaf_hash]);
+    assert_eq!(single.root_node().unwrap().hash.capacity(), 128);
+    single.compact();
+    assert_eq!(single.root_node().unwrap().hash.capacity(), HASH_LEN);
+}
+
+#[test]
+fn test_first_and_last_proofs() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]);
+
+    let (proof, proof_path) = tree.first_proof().unwrap();
+    assert_eq!(proof_path, vec![false, false, false]);
+    assert!(tree.verify(b"a", &proof, &proof_path));
+
+    let (proof, proof_path) = tree.last_proof().unwrap();
+    assert_eq!(proof_path, vec![true]);
+    assert_eq!(proof, vec![MerkleTree::from_strs(&["a", "b", "c", "d"]).root_hash().unwrap()]);
+    assert!(tree.verify(b"e", &proof, &proof_path));
+
+    let empty = MerkleTree::new(Vec::new());
+    assert!(empty.first_proof().is_none());
+    assert!(empty.last_proof().is_none());
+}
+
+#[test]
+fn test_stats_on_tree_with_promotion() {
+    // 6 -> 3 -> 2 -> 1, with the third node of the second level promoted
+    let stats = MerkleTree::from_strs(&["a", "b", "c", "d", "e", "f"]).stats();
+    assert_eq!(
+        stats,
+        TreeStats {
+            leaf_count: 6,
+            height: 3,
+            node_count: 11,
+            promoted_nodes: 1,
+            is_perfect: false,
+        }
+    );
+
+    let perfect = MerkleTree::from_strs(&["a", "b", "c", "d"]).stats();
+    assert!(perfect.is_perfect);
+    assert_eq!(perfect.promoted_nodes, 0);
+    assert_eq!(MerkleTree::new(Vec::new()).stats().node_count, 0);
+}
+
+#[test]
+fn test_keyed_tree_depends_on_key() {
+    let data = blocks(&["a", "b", "c"]);
+    let tree = MerkleTree::builder().keyed(b"secret".to_vec()).build(data.clone());
+    let wrong_key = MerkleTree::builder().keyed(b"guess".to_vec()).build(data.clone());
+    assert_ne!(tree.root_hash(), wrong_key.root_hash());
+    assert_ne!(tree.root_hash(), MerkleTree::new(data).root_hash());
+
+    let (proof, proof_path) = tree.generate_proof(1).unwrap();
+    assert!(tree.verify(b"b", &proof, &proof_path));
+    assert!(!wrong_key.verify(b"b", &proof, &proof_path));
+
+    assert!(format!("{:?}", tree).contains("HmacSha256(..)"));
+    assert!(MerkleTree::from_bytes(&tree.to_bytes().unwrap()).is_err());
+}
+
+#[test]
+fn test_verify_compact_proof() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]);
+    let root = tree.root_hash().unwrap();
+    for (index, data) in ["a", "b", "c", "d", "e"].iter().enumerate() {
+        let proof = tree.proof(index).unwrap();
+        let compact = proof.to_compact().unwrap();
+        assert_eq!(verify_compact(&root, data.as_bytes(), &compact), Ok(true));
+        assert_eq!(verify_compact(&root, b"x", &compact), Ok(false));
+    }
+
+    let compact = tree.proof(4).unwrap().to_compact().unwrap();
+    assert_eq!(compact.len(), 1 + 1 + HASH_LEN);
+    assert_eq!(compact[1], 0b1);
+    assert!(matches!(
+        verify_compact(&root, b"e", &compact[..compact.len() - 1]),
+        Err(MerkleError::BadFormat(_))
+    ));
+    assert!(matches!(verify_compact(&root, b"e", &[]), Err(MerkleError::BadFormat(_))));
+}
+
+#[test]
+fn test_bitcoin_partial_tree_from_merkleblock() {
+    // The `merkleblock` example of the Bitcoin developer reference: 7
+    // transactions, one of them matched
+    let root = hex::decode("7f16c5962e8bd963659c793ce370d95f093bc7e367117b3c30c1f8fdd0d97287")
+        .unwrap();
+    let hashes = hex::decode(concat!(
+        "3612262624047ee87660be1a707519a443b1c1ce3d248cbfc6c15870f6c5daa2",
+        "019f5b01d4195ecbc9398fbf3c3b1fa9bb3183301d7a1fb3bd174fcfa40a2b65",
+        "41ed70551dd7e841883ab8f0b16bf04176b7d1480e4f0af9f3d4c3595768d068",
+        "20d2a7bc994987302e5b1ac80fc425fe25f8b63169ea78e68fbaaefa59379bbf",
+    ))
+    .unwrap();
+
+    let tree = BitcoinPartialMerkleTree::from_bitcoin_bytes(&[0x1d], &hashes, 7).unwrap();
+    assert_eq!(PartialMerkleTree::from_bitcoin_bytes(&[0x1d], &hashes, 7), Ok(tree.clone()));
+    assert_eq!(tree.verify(&root), Some(vec![hashes[32..64].to_vec()]));
+    assert_eq!(tree.verify(&hashes[..32]), None);
+
+    assert!(BitcoinPartialMerkleTree::from_bitcoin_bytes(&[0x1d], &hashes[..96], 7).is_err());
+    assert!(BitcoinPartialMerkleTree::from_bitcoin_bytes(&[0x1d, 0], &hashes, 7).is_err());
+    assert!(BitcoinPartialMerkleTree::from_bitcoin_bytes(&[0x1d], &hashes, 0).is_err());
+}
+
+#[test]
+fn test_root_history_of_appends() {
+    let mut tree = MerkleTree::from_strs(&["a", "b", "c"]);
+    let mut roots = vec![tree.root_hash().unwrap()];
+    let mut proofs = Vec::new();
+    for appended in [&["d"][..], &["e", "f"], &["g", "h", "i"]] {
+        let old_size = tree.leaf_count();
+        for data in appended {
+            tree.push(data.as_bytes());
+        }
+        roots.push(tree.root_hash().unwrap());
+        proofs.push(tree.consistency_proof(old_size).unwrap());
+    }
+
+    let mut size = 3;
+    for (step, proof) in proofs.iter().enumerate() {
+        assert_eq!(proof.old_size, size);
+        assert_eq!(root_history_delta(&roots[step], &roots[step + 1], proof), Some(step + 1));
+        size = proof.new_size;
+    }
+    assert_eq!(size, 9);
+
+    assert_eq!(root_history_delta(&roots[1], &roots[2], &proofs[0]), None);
+    assert_eq!(root_history_delta(&roots[0], &roots[2], &proofs[1]), None);
+    assert_eq!(root_history_delta(&roots[1], &roots[1], &proofs[1]), None);
+}
+
+#[test]
+fn test_prove_at_version_after_appends() {
+    let data: Vec<Vec<u8>> = (0..11).map(|i| format!("leaf {}", i).into_bytes()).collect();
+    let mut tree = MerkleTree::new(data[..4].to_vec());
+    let version_4_root = tree.root_hash().unwrap();
+    for block in &data[4..] {
+        tree.push(block);
+    }
+    assert_eq!(tree.root_at_version(4), Some(version_4_root.clone()));
+
+    for (index, block) in data[..4].iter().enumerate() {
+        let proof = tree.prove_at_version(index, 4).unwrap();
+        assert_eq!(proof.leaf_index, index);
+        assert!(verify_proof(&version_4_root, block, &proof.siblings, &proof.path_bits()));
+        assert!(!tree.verify(block, &proof.siblings, &proof.path_bits()));
+    }
+
+    for version in 1..=data.len() {
+        let old = MerkleTree::new(data[..version].to_vec());
+        assert_eq!(tree.root_at_version(version), old.root_hash());
+        for index in 0..version {
+            assert_eq!(tree.prove_at_version(index, version), old.proof(index));
+        }
+    }
+    assert!(tree.prove_at_version(4, 4).is_none());
+    assert!(tree.prove_at_version(0, 12).is_none());
+    assert!(tree.root_at_version(0).is_none());
+}
+
+#[test]
+fn test_consistency_proofs_between_all_sizes() {
+    let data: Vec<Vec<u8>> = (0..17).map(|i| format!("leaf {}", i).into_bytes()).collect();
+    for new_size in 1..=data.len() {
+        let tree = MerkleTree::new(data[..new_size].to_vec());
+        let new_root = tree.root_hash().unwrap();
+        for old_size in 1..=new_size {
+            let old_root = MerkleTree::new(data[..old_size].to_vec()).root_hash().unwrap();
+            let proof = tree.consistency_proof(old_size).unwrap();
+            assert_eq!(
+                root_history_delta(&old_root, &new_root, &proof),
+                Some(new_size - old_size),
+                "{} -> {}",
+                old_size,
+                new_size
+            );
+            if old_size < new_size {
+                assert_eq!(root_history_delta(&new_root, &new_root, &proof), None);
+            }
+        }
+        assert!(tree.consistency_proof(0).is_none());
+        assert!(tree.consistency_proof(new_size + 1).is_none());
+    }
+}
+
+#[test]
+fn test_root_progression_yields_root_per_leaf() {
+    let data: Vec<Vec<u8>> = (0..11).map(|i| format!("leaf {}", i).into_bytes()).collect();
+    let roots: Vec<Hash> = RootProgression::new(&data).collect();
+    assert_eq!(roots.len(), data.len());
+    assert_eq!(roots.last(), MerkleTree::new(data.clone()).root_hash().as_ref());
+    for (count, root) in roots.iter().enumerate() {
+        assert_eq!(Some(root), MerkleTree::new(data[..=count].to_vec()).root_hash().as_ref());
+    }
+    assert_eq!(RootProgression::new(Vec::<Vec<u8>>::new()).count(), 0);
+}
+
+#[test]
+fn test_proof_to_solidity() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c"]);
+    let (siblings, path) = tree.proof(1).unwrap().to_solidity();
+    assert_eq!(
+        siblings,
+        format!("[\"0x{}\",\"0x{}\"]", hex::encode(hash("a")), hex::encode(hash("c")))
+    );
+    assert_eq!(path, "[true,false]");
+
+    let (siblings, path) = MerkleTree::from_strs(&["a"]).proof(0).unwrap().to_solidity();
+    assert_eq!((siblings.as_str(), path.as_str()), ("[]", "[]"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_proof_from_json_strict() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c"]);
+    let proof = tree.proof(1).unwrap();
+    let json = format!(
+        r#"{{"leaf_index":1,"siblings":["{}","{}"],"path":["right","left"]}}"#,
+        hex::encode(hash("a")),
+        hex::encode(hash("c"))
+    );
+    assert_eq!(proof.to_json(), json);
+    assert_eq!(Proof::from_json_strict(&json), Ok(proof));
+
+    let extra_field = json.replacen('{', r#"{"root":"00","#, 1);
+    let non_hex = json.replacen(&hex::encode(hash("a")), &"zz".repeat(HASH_LEN), 1);
+    let uppercase = json.replacen(&hex::encode(hash("a")), &hex::encode_upper(hash("a")), 1);
+    let negative_index = json.replacen("\"leaf_index\":1", "\"leaf_index\":-1", 1);
+    let string_index = json.replacen("\"leaf_index\":1", "\"leaf_index\":\"1\"", 1);
+    let short_path = json.replacen(r#"["right","left"]"#, r#"["right"]"#, 1);
+    let bad_side = json.replacen("\"right\"", "\"up\"", 1);
+    let rejected = [
+        extra_field, non_hex, uppercase, negative_index, string_index, short_path, bad_side,
+    ];
+    for bad in rejected {
+        assert!(Proof::from_json_strict(&bad).is_err(), "{}", bad);
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_round_trips_for_every_shape() {
+    for side in [Side::Left, Side::Right] {
+        let json = serde_json::to_string(&side).unwrap();
+        assert_eq!(serde_json::from_str::<Side>(&json).unwrap(), side);
+    }
+    assert_eq!(serde_json::to_string(&Side::Right).unwrap(), "\"right\"");
+
+    for leaf_count in 1..=17 {
+        let data: Vec<Vec<u8>> = (0..leaf_count)
+            .map(|i| format!("leaf {}", i).into_bytes())
+            .collect();
+        let tree = MerkleTree::new(data.clone());
+        let root = tree.root_hash().unwrap();
+        for (index, block) in data.iter().enumerate() {
+            let proof = tree.proof(index).unwrap();
+            let parsed = Proof::from_json_strict(&proof.to_json()).unwrap();
+            assert_eq!(parsed, proof);
+            assert!(verify_proof(&root, block, &parsed.siblings, &parsed.path_bits()));
+        }
+    }
+}
+
+#[test]
+fn test_verify_proof_needs_no_tree_size() {
+    for leaf_count in [1, 2, 5, 16, 33] {
+        let data: Vec<Vec<u8>> = (0..leaf_count).map(|i| vec![i as u8]).collect();
+        // Only the root and the proof reach the verifier
+        let (root, proofs) = {
+            let tree = MerkleTree::new(data.clone());
+            let proofs: Vec<_> = (0..leaf_count).map(|i| tree.generate_proof(i).unwrap()).collect();
+            (tree.root_hash().unwrap(), proofs)
+        };
+        for (block, (proof, mut proof_path)) in data.iter().zip(proofs) {
+            assert!(verify_proof(&root, block, &proof, &proof_path));
+            proof_path.push(false);
+            assert!(!verify_proof(&root, block, &proof, &proof_path));
+        }
+    }
+}
+
+#[test]
+fn test_root_hash_try_from_bytes() {
+    let bytes = MerkleTree::from_strs(&["a", "b"]).root_hash().unwrap();
+    let root = RootHash::try_from(&bytes[..]).unwrap();
+    assert_eq!(root.as_bytes()[..], bytes[..]);
+    assert_eq!(root.to_string().parse::<RootHash>(), Ok(root));
+    assert_eq!(
+        RootHash::try_from(&bytes[..31]),
+        Err(MerkleError::InvalidRootLength { len: 31, expected: HASH_LEN })
+    );
+    assert!(RootHash::try_from(&[bytes.clone(), vec![0]].concat()[..]).is_err());
+    assert!("zz".parse::<RootHash>().is_err());
+}
+
+#[test]
+fn test_flat_proof_round_trip_and_verify() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]);
+    let root = tree.root_hash().unwrap();
+    let proof = tree.proof(2).unwrap();
+
+    let flat = proof.to_flat_bytes().unwrap();
+    assert_eq!(flat.len(), 1 + 1 + 3 * HASH_LEN);
+    assert_eq!(&flat[..2], &[3, 0b010]);
+    assert_eq!(Proof::from_flat_bytes(&flat, 2), Ok(proof));
+
+    assert!(verify_flat(&root, b"c", &flat));
+    assert!(!verify_flat(&root, b"x", &flat));
+    assert!(!verify_flat(&root, b"c", &flat[..flat.len() - 1]));
+
+    let single = MerkleTree::from_strs(&["a"]).proof(0).unwrap().to_flat_bytes().unwrap();
+    assert_eq!(single, [0]);
+    assert!(verify_flat(&hash("a"), b"a", &single));
+}
+
+#[test]
+fn test_proof_round_trips_without_tree() {
+    let bytes = {
+        let tree = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]);
+        let proof = tree.proof(2).unwrap();
+        assert_eq!(proof.leaf_index, 2);
+        assert_eq!(proof.path, vec![Side::Left, Side::Right, Side::Left]);
+        proof.to_bytes().unwrap()
+    };
+
+    let proof = Proof::from_bytes(&bytes).unwrap();
+    assert_eq!(proof.leaf_index, 2);
+    let root = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]).root_hash().unwrap();
+    assert!(verify_proof(&root, b"c", &proof.siblings, &proof.path_bits()));
+    assert_eq!(proof.to_bytes().unwrap(), bytes);
+    assert!(matches!(Proof::from_bytes(&bytes[..7]), Err(MerkleError::BadFormat(_))));
+}
+
+#[test]
+fn test_rebuild_from_leaves() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]);
+    let stored: Vec<Hash> = tree.leaves().map(<[u8]>::to_vec).collect();
+    let rebuilt = MerkleTree::rebuild_from_leaves(stored);
+    assert_eq!(rebuilt.root_hash(), tree.root_hash());
+    assert_eq!(rebuilt.leaf_count(), 5);
+    assert!(rebuilt.leaves().eq(tree.leaves()));
+}
+
+#[test]
+fn test_metadata_does_not_affect_root() {
+    let stamped = MerkleTree::with_metadata(vec![
+        (b"a".to_vec(), 1_700_000_000u64),
+        (b"b".to_vec(), 1_700_000_060),
+        (b"c".to_vec(), 1_700_000_120),
+    ]);
+    let sourced = MerkleTree::with_metadata(vec![
+        (b"a".to_vec(), "node-1"),
+        (b"b".to_vec(), "node-2"),
+        (b"c".to_vec(), "node-3"),
+    ]);
+    assert_eq!(stamped.root_hash(), sourced.root_hash());
+    assert_eq!(stamped.root_hash(), MerkleTree::from_strs(&["a", "b", "c"]).root_hash());
+
+    let (proof, proof_path) = sourced.generate_proof(1).unwrap();
+    assert!(sourced.verify(b"b", &proof, &proof_path));
+    assert_eq!(sourced.metadata(1), Some(&"node-2"));
+    assert_eq!(sourced.metadata(3), None);
+
+    let mut sourced = sourced;
+    sourced.delete_leaf(0).unwrap();
+    sourced.push_with_metadata(b"d", "node-4");
+    assert_eq!(sourced.metadata(0), Some(&"node-2"));
+    assert_eq!(sourced.metadata(2), Some(&"node-4"));
+}
+
+#[test]
+fn test_verify_and_locate() {
+    let data = ["a", "b", "c", "d", "e", "f", "g", "h"];
+    let tree = MerkleTree::from_strs(&data);
+    let (proof, proof_path) = tree.generate_proof(3).unwrap();
+    assert_eq!(tree.verify_and_locate(b"d", &proof, &proof_path), Some(3));
+    assert_eq!(tree.verify_and_locate(b"x", &proof, &proof_path), None);
+
+    // Paths that skip promoted levels still decode, and every path round-trips
+    for leaf_count in 1..=20 {
+        for index in 0..leaf_count {
+            let proof_path = proof_path_for(index, leaf_count);
+            assert_eq!(leaf_index_for_path(&proof_path, leaf_count), Some(index));
+        }
+    }
+    assert_eq!(leaf_index_for_path(&[false, false], 8), None);
+    assert_eq!(leaf_index_for_path(&[], 0), None);
+}
+
+#[test]
+fn test_from_map_ignores_insertion_order() {
+    let mut forwards = BTreeMap::new();
+    forwards.insert("alice", "10");
+    forwards.insert("bob", "20");
+    forwards.insert("carol", "30");
+
+    let mut backwards = BTreeMap::new();
+    backwards.insert("carol", "30");
+    backwards.insert("bob", "20");
+    backwards.insert("alice", "10");
+
+    let tree = MerkleTree::from_map(&forwards);
+    assert_eq!(tree.root_hash(), MerkleTree::from_map(&backwards).root_hash());
+    assert_eq!(
+        tree.root_hash(),
+        MerkleTree::from_strs(&["alice10", "bob20", "carol30"]).root_hash()
+    );
+
+    backwards.insert("bob", "21");
+    assert_ne!(tree.root_hash(), MerkleTree::from_map(&backwards).root_hash());
+}
+
+#[test]
+fn test_verify_unordered_openzeppelin_fixture() {
+    use sha3::Keccak256;
+
+    // Sorted-pair Keccak-256 tree over keccak256("a"), ..., keccak256("d"),
+    // proving the leaf for "c"
+    let leaf = hex::decode("0b42b6393c1f53060fe3ddbfcd7aadcca894465a5a438f69c87d790b2299b9b2")
+        .unwrap();
+    let proof = vec![
+        hex::decode("f1918e8562236eb17adc8502332f4c9c82bc14e19bfc0aa10ab674ff75b3d2f3").unwrap(),
+        hex::decode("805b21d846b189efaeb0377d6bb0d201b3872a363e607c25088f025b0c6ae1f8").unwrap(),
+    ];
+    let root = hex::decode("68203f90e9d07dc5859259d7536e87a6ba9d345f2552b5b9de2999ddce9ce1bf")
+        .unwrap();
+    assert_eq!(leaf, Keccak256::digest(b"c").to_vec());
+    assert!(verify_unordered_with::<Keccak256>(&root, &leaf, &proof));
+
+    let reversed: Vec<Hash> = proof.iter().rev().cloned().collect();
+    assert!(!verify_unordered_with::<Keccak256>(&root, &leaf, &reversed));
+    assert!(!verify_unordered(&root, &leaf, &proof));
+
+    // SHA-256 pairs are sorted the same way
+    let (a, b) = (hash("a"), hash("b"));
+    let sorted_root = if a <= b { hash_pair(&a, &b) } else { hash_pair(&b, &a) };
+    assert!(verify_unordered(&sorted_root, &b, &[a]));
+}
+
+#[test]
+fn test_from_leaf_readers_streams_large_leaves() {
+    const LEAF_SIZE: u64 = 3 * 1024 * 1024 + 17;
+
+    let tree = MerkleTree::from_leaf_readers(4, |index| {
+        Ok(io::repeat(index as u8).take(LEAF_SIZE))
+    })
+    .unwrap();
+
+    let data_blocks = (0..4u8).map(|byte| vec![byte; LEAF_SIZE as usize]).collect();
+    assert_eq!(tree.root_hash(), MerkleTree::new(data_blocks).root_hash());
+
+    let failing = MerkleTree::from_leaf_readers(2, |index| {
+        if index == 1 {
+            Err(io::Error::new(io::ErrorKind::NotFound, "missing leaf"))
+        } else {
+            Ok(io::empty())
+        }
+    });
+    assert_eq!(failing.unwrap_err().kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_same_leaves_across_hashers() {
+    let data = blocks(&["a", "b", "c"]);
+    let sha256 = MerkleTree::builder().retain_leaf_data().build(data.clone());
+    let mut sha512 = MerkleTree::builder()
+        .leaf_hasher(HashAlgorithm::Sha512)
+        .node_hasher(HashAlgorithm::Sha512)
+        .retain_leaf_data()
+        .build(data.clone());
+
+    assert!(sha256.same_leaves(&sha512));
+    assert_ne!(sha256.root_hash(), sha512.root_hash());
+    assert_eq!(sha512.leaf_data(2), Some(&b"c"[..]));
+
+    sha512.update_leaf(1, b"x").unwrap();
+    assert!(!sha256.same_leaves(&sha512));
+
+    // Trees that only keep hashes cannot be compared by their data
+    assert!(!sha256.same_leaves(&MerkleTree::new(data)));
+}
+
+#[test]
+fn test_insert_leaf_matches_fresh_build() {
+    let mut tree = MerkleTree::from_strs(&["a", "b", "d", "e"]);
+    tree.insert_leaf(2, b"c").unwrap();
+    assert_eq!(tree.root_hash(), MerkleTree::from_strs(&["a", "b", "c", "d", "e"]).root_hash());
+    let (proof, proof_path) = tree.generate_proof(2).unwrap();
+    assert!(tree.verify(b"c", &proof, &proof_path));
+
+    tree.insert_leaf(5, b"f").unwrap();
+    assert_eq!(tree.leaf_count(), 6);
+    assert_eq!(
+        tree.insert_leaf(7, b"x"),
+        Err(MerkleError::IndexOutOfRange { index: 7, leaf_count: 6 })
+    );
+}
+
+#[test]
+fn test_from_borrowed_slices() {
+    let buffer = b"alphabetagamma";
+    let slices: [&[u8]; 3] = [&buffer[..5], &buffer[5..9], &buffer[9..]];
+    let tree = MerkleTree::from_borrowed(&slices);
+    assert_eq!(tree.root_hash(), MerkleTree::from_strs(&["alpha", "beta", "gamma"]).root_hash());
+
+    let cows: Vec<Cow<[u8]>> = vec![
+        Cow::Borrowed(slices[0]),
+        Cow::Owned(b"beta".to_vec()),
+        Cow::Borrowed(slices[2]),
+    ];
+    assert_eq!(MerkleTree::from_borrowed(&cows).root_hash(), tree.root_hash());
+}
+
+#[test]
+fn test_from_bytes_rejects_non_canonical_nodes() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]);
+    let bytes = tree.to_bytes_with_nodes().unwrap();
+    let loaded = MerkleTree::from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.root_hash(), tree.root_hash());
+    assert_eq!(loaded.generate_proof(4), tree.generate_proof(4));
+
+    // Three leaves paired as (a, (b, c)) instead of the canonical ((a, b), c),
+    // with every stored hash consistent with the shape
+    let (a, b, c) = (hash("a"), hash("b"), hash("c"));
+    let bc = hash_pair(&b, &c);
+    let root = hash_pair(&a, &bc);
+    let mut crafted = MerkleTree::from_strs(&["a", "b", "c"]).to_bytes_with_nodes().unwrap();
+    crafted.truncate(18);
+    for (tag, node_hash) in [(1, &root), (0, &a), (1, &bc), (0, &b), (0, &c)] {
+        crafted.push(tag);
+        crafted.extend_from_slice(node_hash);
+    }
+    assert_eq!(
+        MerkleTree::from_bytes(&crafted).unwrap_err(),
+        MerkleError::BadFormat("tree shape is not canonical for its leaf count")
+    );
+
+    // The canonical shape with a tampered internal hash
+    let mut tampered = bytes.clone();
+    tampered[18 + 1] ^= 1;
+    assert_eq!(
+        MerkleTree::from_bytes(&tampered).unwrap_err(),
+        MerkleError::BadFormat("internal node hash does not match its children")
+    );
+}
+
+#[test]
+fn test_leaf_builder_matches_hashing_concatenation() {
+    let mut builder = Node::new_leaf_builder();
+    builder.update(b"header:");
+    builder.update(b"body:");
+    digest::Update::update(&mut builder, b"trailer");
+    let leaf = builder.finish();
+
+    let whole = MerkleTree::from_strs(&["header:body:trailer"]);
+    assert_eq!(leaf.hash(), whole.root_node().unwrap().hash());
+    assert!(leaf.children().is_none());
+}
+
+#[test]
+fn test_prune_to_keeps_only_requested_proofs() {
+    let data = ["a", "b", "c", "d", "e", "f", "g", "h"];
+    let full = MerkleTree::from_strs(&data);
+    let mut tree = MerkleTree::from_strs(&data);
+    tree.prune_to(&[0, 3]).unwrap();
+
+    assert_eq!(tree.root_hash(), full.root_hash());
+    for index in [0, 3] {
+        let (proof, proof_path) = tree.generate_proof(index).unwrap();
+        assert_eq!(Some((proof.clone(), proof_path.clone())), full.generate_proof(index));
+        assert!(tree.verify(data[index].as_bytes(), &proof, &proof_path));
+    }
+    assert_eq!(tree.generate_proof(1), None);
+    assert_eq!(tree.generate_proof(5), None);
+
+    // The right half is reduced to its root hash
+    assert_eq!(tree.all_node_hashes().len(), 9);
+    assert!(tree.root_node().unwrap().children().unwrap().1.children().is_none());
+
+    tree.prune_to(&[3, 5]).unwrap();
+    assert!(tree.generate_proof(3).is_some());
+    assert_eq!(tree.generate_proof(0), None);
+    assert_eq!(tree.generate_proof(5), None);
+    assert_eq!(
+        tree.prune_to(&[8]),
+        Err(MerkleError::IndexOutOfRange { index: 8, leaf_count: 8 })
+    );
+}
+
+#[test]
+#[should_panic(expected = "the tree has been pruned")]
+fn test_pruned_tree_cannot_be_mutated() {
+    let mut tree = MerkleTree::from_strs(&["a", "b", "c"]);
+    tree.prune_to(&[0]).unwrap();
+    tree.push(b"d");
+}
+
+#[test]
+fn test_reused_hasher_matches_per_level_hashing() {
+    let tree = MerkleTree::builder()
+        .leaf_hasher(HashAlgorithm::Sha512)
+        .node_hasher(HashAlgorithm::Sha512)
+        .build(blocks(&["a", "b", "c", "d", "e"]));
+    for index in 0..5 {
+        let (proof, proof_path) = tree.generate_proof(index).unwrap();
+        let leaf_hash = tree.hasher.hash_leaf(&[b'a' + index as u8]);
+
+        let mut expected = leaf_hash.clone();
+        for (sibling_hash, &is_right) in proof.iter().zip(&proof_path) {
+            expected = if is_right {
+                HashAlgorithm::Sha512.hash(&[sibling_hash, &expected])
+            } else {
+                HashAlgorithm::Sha512.hash(&[&expected, sibling_hash])
+            };
+        }
+        assert_eq!(fold_proof::<Sha512>(leaf_hash, &proof, &proof_path), expected);
+        assert_eq!(Some(expected), tree.root_hash());
+    }
+}
+
+#[test]
+fn test_borrowed_root_matches_cloned_root() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c"]);
+    assert_eq!(tree.root().map(<[u8]>::to_vec), tree.root_hash());
+    assert_eq!(tree.root().unwrap(), tree.root_node().unwrap().hash());
+    assert_eq!(MerkleTree::new(Vec::new()).root(), None);
+}
+
+#[test]
+fn test_mountain_range_proofs_survive_appends() {
+    let mut mmr = MerkleMountainRange::new();
+    for data in ["a", "b", "c"] {
+        mmr.push(data.as_bytes());
+    }
+    let (proof, proof_path) = mmr.generate_proof(2).unwrap();
+    assert!(proof.is_empty());
+    let (old_proof, old_path) = mmr.generate_proof(1).unwrap();
+    assert_eq!(mmr.peaks().len(), 2);
+
+    for data in ["d", "e", "f", "g", "h", "i"] {
+        mmr.push(data.as_bytes());
+    }
+    assert_eq!(mmr.leaf_count(), 9);
+    assert_eq!(mmr.peaks().len(), 2);
+    assert!(mmr.verify(2, b"c", &proof, &proof_path));
+    assert!(mmr.verify(1, b"b", &old_proof, &old_path));
+    assert!(!mmr.verify(0, b"b", &old_proof, &old_path));
+    assert!(!mmr.verify(1, b"x", &old_proof, &old_path));
+
+    // A fresh proof reaches the 8-leaf peak, whose hash is the balanced root
+    let (new_proof, new_path) = mmr.generate_proof(1).unwrap();
+    assert_eq!(new_proof.len(), 3);
+    assert!(mmr.verify(1, b"b", &new_proof, &new_path));
+    let eight = MerkleTree::from_strs(&["a", "b", "c", "d", "e", "f", "g", "h"]);
+    assert_eq!(mmr.peaks()[0], eight.root().unwrap());
+    assert_eq!(mmr.root(), Some(hash_pair(eight.root().unwrap(), &hash("i"))));
+}
+
+#[test]
+fn test_partial_tree_verified_ranges() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d", "e"]);
+    let root = tree.root_hash().unwrap();
+
+    let mut partial = PartialMerkleTree::new(root.clone(), 5);
+    partial.add_subtree(2..3, hash("c")).unwrap();
+    partial.add_subtree(3..4, hash("d")).unwrap();
+    assert!(partial.verified_ranges().is_empty());
+    partial.add_subtree(0..2, hash_pair(&hash("a"), &hash("b"))).unwrap();
+    partial.add_subtree(4..5, hash("x")).unwrap();
+    assert!(partial.verified_ranges().is_empty());
+    partial.add_subtree(4..5, hash("e")).unwrap();
+    assert_eq!(partial.verified_ranges(), vec![0..5]);
+    partial.add_subtree(1..2, hash("b")).unwrap();
+    assert_eq!(partial.verified_ranges(), vec![0..5]);
+
+    assert_eq!(
+        partial.add_subtree(1..3, hash("b")),
+        Err(MerkleError::NotASubtree { start: 1, end: 3 })
+    );
+    assert!(partial.add_subtree(4..6, hash("e")).is_err());
+}
+
+#[test]
+fn test_partial_tree_verifies_two_subtree_hashes() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d"]);
+    let mut partial = PartialMerkleTree::new(tree.root_hash().unwrap(), 4);
+    partial.add_subtree(0..2, tree.subtree_root(0, 2).unwrap()).unwrap();
+    assert!(partial.verified_ranges().is_empty());
+    partial.add_subtree(2..4, tree.subtree_root(2, 4).unwrap()).unwrap();
+    assert_eq!(partial.verified_ranges(), vec![0..4]);
+}
+
+#[test]
+fn test_partial_tree_passes_over_a_bad_subtree_hash() {
+    let tree = MerkleTree::from_strs(&["a", "b", "c", "d"]);
+    let mut partial = PartialMerkleTree::new(tree.root_hash().unwrap(), 4);
+    for (i, leaf) in ["a", "b", "c", "d"].iter().enumerate() {
+        partial.add_subtree(i..i + 1, hash(leaf)).unwrap();
+    }
+    assert_eq!(partial.verified_ranges(), vec![0..4]);
+
+    // A bad hash over good leaves does not hide them
+    partial.add_subtree(0..2, vec![0; 32]).unwrap();
+    assert_eq!(partial.verified_ranges(), vec![0..4]);
+
+    // A good hash over a bad leaf still ties to the root
+    partial.add_subtree(0..2, tree.subtree_root(0, 2).unwrap()).unwrap();
+    partial.add_subtree(1..2, hash("x")).unwrap();
+    assert_eq!(partial.verified_ranges(), vec![0..4]);
+
+    // Without one, the bad leaf leaves its sibling unverified too
+    partial.add_subtree(3..4, hash("x")).unwrap();
+    assert!(partial.verified_ranges().is_empty());
+}
+
+#[test]
+fn test_empty_partial_tree_verifies_nothing() {
+    let mut empty = PartialMerkleTree::new(hash("a"), 0);
+    assert!(empty.verified_ranges().is_empty());
+    assert!(empty.add_subtree(0..0, hash("a")).is_err());
+    assert!(empty.add_subtree(0..1, hash("a")).is_err());
+    assert!(empty.verified_ranges().is_empty());
+}
+
+#[test]
+fn test_try_from_whitespace_separated_str() {
+    let tree = MerkleTree::try_from("a b").unwrap();
+    assert_eq!(tree.root_hash(), MerkleTree::from_strs(&["a", "b"]).root_hash());
+
+    let spaced = MerkleTree::try_from("  a \t b\n c  ").unwrap();
+    assert_eq!(spaced.root_hash(), MerkleTree::from_strs(&["a", "b", "c"]).root_hash());
+    assert_eq!(MerkleTree::try_from(" \n ").unwrap_err(), MerkleError::EmptyTree);
+}
+
+#[test]
+fn test_prove_range_gap_between_adjacent_keys() {
+    let keys = blocks(&["apple", "banana", "cherry", "damson"]);
+    let tree = SortedMerkleTree::new(keys).unwrap();
+    let root = tree.root_hash().unwrap();
+    let leaf_count = tree.leaf_count();
+
+    // Both bounds are present and nothing sorts between them
+    let proof = tree.prove_range_gap(b"banana", b"cherry").unwrap();
+    assert_eq!(proof.left.as_ref().unwrap().data, b"banana");
+    assert_eq!(proof.right.as_ref().unwrap().data, b"cherry");
+    assert!(SortedMerkleTree::verify_range_gap(&root, leaf_count, b"banana", b"cherry", &proof));
+    assert!(!SortedMerkleTree::verify_range_gap(&root, leaf_count, b"apple", b"cherry", &proof));
+
+    // Bounds that are not keys, and a range running past the last key
+    let proof = tree.prove_range_gap(b"coconut", b"cranberry").unwrap();
+    assert!(SortedMerkleTree::verify_range_gap(
+        &root,
+        leaf_count,
+        b"coconut",
+        b"cranberry",
+        &proof
+    ));
+    let proof = tree.prove_range_gap(b"damson", b"zucchini").unwrap();
+    assert!(proof.right.is_none());
+    assert!(SortedMerkleTree::verify_range_gap(&root, leaf_count, b"damson", b"zucchini", &proof));
+
+    assert!(tree.prove_range_gap(b"apple", b"cherry").is_none());
+    assert!(tree.prove_range_gap(b"cherry", b"banana").is_none());
+}
+
+#[test]
+fn test_prove_range_gap_between_adjacent_entry_keys() {
+    let entries: Vec<(Vec<u8>, Vec<u8>)> = [("apple", "1"), ("banana", "2"), ("cherry", "3")]
+        .iter()
+        .map(|(key, value)| (key.as_bytes().to_vec(), value.as_bytes().to_vec()))
+        .collect();
+    let tree = SortedMerkleTree::from_entries(entries).unwrap();
+    let root = tree.root_hash().unwrap();
+    let leaf_count = tree.leaf_count();
+
+    // One proof shows both entries and that no key sorts between them
+    let proof = tree.prove_range_gap(b"banana", b"cherry").unwrap();
+    let left = decode_entry(&proof.left.as_ref().unwrap().data).unwrap();
+    let right = decode_entry(&proof.right.as_ref().unwrap().data).unwrap();
+    assert_eq!(left, (&b"banana"[..], &b"2"[..]));
+    assert_eq!(right, (&b"cherry"[..], &b"3"[..]));
+    assert!(SortedMerkleTree::verify_entry_range_gap(
+        &root,
+        leaf_count,
+        b"banana",
+        b"cherry",
+        &proof
+    ));
+    assert!(!SortedMerkleTree::verify_entry_range_gap(
+        &root,
+        leaf_count,
+        b"apple",
+        b"cherry",
+        &proof
+    ));
+
+    let absent = tree.prove_absence(b"blueberry").unwrap();
+    assert!(SortedMerkleTree::verify_entry_absence(&root, leaf_count, b"blueberry", &absent));
+    assert!(tree.prove_absence(b"banana").is_none());
+    assert!(tree.prove_range_gap(b"apple", b"cherry").is_none());
+
+    // Ordering is by key, not by the encoded leaf
+    let unsorted = vec![(b"b".to_vec(), Vec::new()), (b"ab".to_vec(), Vec::new())];
+    assert_eq!(
+        SortedMerkleTree::from_entries(unsorted).unwrap_err(),
+        MerkleError::UnsortedLeaves { index: 1 }
+    );
+    assert_ne!(encode_entry(b"ab", b"c"), encode_entry(b"a", b"bc"));
+}
@@ -0,0 +1,65 @@
+//
+// Building a tree from a stream of bytes
+//
+// `MerkleWriter` is an `io::Write` sink that cuts whatever is written to it
+// into fixed-size chunks and hashes each chunk as a leaf as soon as it is
+// complete, so any reader can be fed into a tree with `io::copy`.
+//
+
+use crate::{MerkleTree, Node, TreeHasher};
+use std::io::{self, Write};
+
+/// An `io::Write` sink that builds a tree with one leaf per `chunk_size` bytes
+#[derive(Debug)]
+pub struct MerkleWriter {
+    chunk_size: usize,
+    buffer: Vec<u8>,
+    leaves: Vec<Node>,
+    hasher: TreeHasher,
+}
+
+impl MerkleWriter {
+    /// Create a writer that emits a leaf every `chunk_size` bytes
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn new(chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must be non-zero");
+
+        MerkleWriter {
+            chunk_size,
+            buffer: Vec::with_capacity(chunk_size),
+            leaves: Vec::new(),
+            hasher: TreeHasher::default(),
+        }
+    }
+
+    /// Build the tree, turning any buffered bytes into a final, shorter leaf
+    pub fn finish(mut self) -> MerkleTree {
+        if !self.buffer.is_empty() {
+            self.leaves.push(Node::new_leaf(&self.buffer, &self.hasher));
+        }
+        MerkleTree::build(self.leaves, self.hasher)
+    }
+}
+
+impl Write for MerkleWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let take = (self.chunk_size - self.buffer.len()).min(rest.len());
+            self.buffer.extend_from_slice(&rest[..take]);
+            rest = &rest[take..];
+
+            if self.buffer.len() == self.chunk_size {
+                self.leaves.push(Node::new_leaf(&self.buffer, &self.hasher));
+                self.buffer.clear();
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // A partial chunk stays buffered until more data or `finish`
+        Ok(())
+    }
+}